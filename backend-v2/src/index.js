@@ -33,6 +33,16 @@ const TOKENS = ["ETH", "BTC", "SOL", "MATIC", "LINK"];
 // Update interval (2 seconds)
 const UPDATE_INTERVAL = 2000;
 
+// On-chain prices are fixed-point: mantissa * 10^-decimals
+const PRICE_DECIMALS = 8;
+
+const toLineraPrice = (value) => ({
+  mantissa: Math.round(value * 10 ** PRICE_DECIMALS),
+  decimals: PRICE_DECIMALS,
+});
+
+const fromLineraPrice = (price) => price.mantissa / 10 ** price.decimals;
+
 class SynapseNetBackend {
   constructor() {
     this.app = express();
@@ -257,12 +267,17 @@ class SynapseNetBackend {
           query: `
             query GetUserAlerts($userId: String!) {
               userAlerts(userId: $userId) {
-                id
-                token
-                thresholdType
-                thresholdValue
-                active
-                createdAt
+                nodes {
+                  id
+                  token
+                  thresholdType
+                  thresholdValue {
+                    mantissa
+                    decimals
+                  }
+                  active
+                  createdAt
+                }
               }
             }
           `,
@@ -279,7 +294,7 @@ class SynapseNetBackend {
 
         if (response.ok) {
           const data = await response.json();
-          const alerts = data.data?.userAlerts || [];
+          const alerts = data.data?.userAlerts?.nodes || [];
           
           // Transform to frontend format
           const active = alerts
@@ -288,7 +303,7 @@ class SynapseNetBackend {
               id: a.id,
               token: a.token,
               condition: a.thresholdType.toLowerCase(),
-              value: a.thresholdValue,
+              value: fromLineraPrice(a.thresholdValue),
               active: a.active,
               created: new Date(a.createdAt / 1000).toLocaleString()
             }));
@@ -336,7 +351,7 @@ class SynapseNetBackend {
               id: alertId,
               token: token.toUpperCase(),
               thresholdType: condition === "above" ? "ABOVE" : "BELOW",
-              thresholdValue: parseFloat(value),
+              thresholdValue: toLineraPrice(parseFloat(value)),
               active: true,
               createdAt: timestamp
             }
@@ -571,7 +586,7 @@ class SynapseNetBackend {
           query: `
             mutation SubmitPrice(
               $token: String!
-              $price: Float!
+              $price: PriceInput!
               $source: String!
               $timestamp: Int!
             ) {
@@ -585,9 +600,10 @@ class SynapseNetBackend {
           `,
           variables: {
             token: aggregated.token,
-            price: input.price,
+            price: toLineraPrice(input.price),
             source: input.source,
-            timestamp: input.timestamp,
+            // Oracle timestamps are in milliseconds, the contract uses microseconds
+            timestamp: input.timestamp * 1000,
           },
         };
 
@@ -627,11 +643,16 @@ class SynapseNetBackend {
         query: `
           query GetUserAlerts($userId: String!) {
             userAlerts(userId: $userId) {
-              id
-              token
-              thresholdType
-              thresholdValue
-              active
+              nodes {
+                id
+                token
+                thresholdType
+                thresholdValue {
+                  mantissa
+                  decimals
+                }
+                active
+              }
             }
           }
         `,
@@ -648,17 +669,18 @@ class SynapseNetBackend {
 
       if (response.ok) {
         const data = await response.json();
-        const alerts = data.data?.userAlerts || [];
+        const alerts = data.data?.userAlerts?.nodes || [];
         
         // Check if any alerts are triggered
         for (const alert of alerts) {
           if (alert.token === token && alert.active) {
+            const threshold = fromLineraPrice(alert.thresholdValue);
             const triggered = 
-              (alert.thresholdType === "ABOVE" && price >= alert.thresholdValue) ||
-              (alert.thresholdType === "BELOW" && price <= alert.thresholdValue);
+              (alert.thresholdType === "ABOVE" && price >= threshold) ||
+              (alert.thresholdType === "BELOW" && price <= threshold);
 
             if (triggered) {
-              console.log(`🔔 Alert triggered: ${token} ${alert.thresholdType} $${alert.thresholdValue} (current: $${price})`);
+              console.log(`🔔 Alert triggered: ${token} ${alert.thresholdType} $${threshold} (current: $${price})`);
               
               // Broadcast alert notification via WebSocket
               this.broadcast({
@@ -667,7 +689,7 @@ class SynapseNetBackend {
                   id: alert.id,
                   token: alert.token,
                   condition: alert.thresholdType.toLowerCase(),
-                  value: alert.thresholdValue,
+                  value: threshold,
                   price: price
                 }
               });
@@ -0,0 +1,331 @@
+//! Pure price aggregation helpers shared by the contract and the service.
+//!
+//! Aggregates are computed on fixed-point mantissas sharing the token's
+//! decimals, so results are reproducible bit for bit on every validator.
+//! Floating point is only used for weights and relative deviations.
+
+use crate::{
+    price::{div_round, Price},
+    Candle, OutlierMethod, PriceConfidence, PricePoint,
+};
+
+/// Scales the MAD to a standard deviation estimate for normally distributed prices
+const MAD_SCALE: f64 = 1.4826;
+
+/// Resolution of the integer weights derived from floating-point ones
+const WEIGHT_UNITS: f64 = 1e9;
+
+/// Median of `values`; the rounded mean of the two middle values for even lengths
+pub fn median(values: &[u64]) -> Option<u64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let mid = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => {
+            let sum = u128::from(sorted[mid - 1]) + u128::from(sorted[mid]);
+            Some(div_round(sum, 2) as u64)
+        }
+        _ => Some(sorted[mid]),
+    }
+}
+
+/// Rounded arithmetic mean of `values`
+pub fn mean(values: &[u64]) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    let sum: u128 = values.iter().map(|&value| u128::from(value)).sum();
+    Some(div_round(sum, values.len() as u128) as u64)
+}
+
+/// Spread of `prices` around their mean, with dispersion relative to `price`
+pub fn price_confidence(prices: &[u64], price: u64, decimals: u8) -> PriceConfidence {
+    let Some(center) = mean(prices) else {
+        return PriceConfidence {
+            stddev: Price::zero(decimals),
+            min: Price::zero(decimals),
+            max: Price::zero(decimals),
+            dispersion: 0.0,
+            sources: 0,
+        };
+    };
+    let squared_deviations = prices.iter().fold(0u128, |sum, &value| {
+        let deviation = u128::from(value.abs_diff(center));
+        sum.saturating_add(deviation.saturating_mul(deviation))
+    });
+    let variance = squared_deviations / prices.len() as u128;
+    let stddev = u64::try_from(variance.isqrt()).unwrap_or(u64::MAX);
+
+    PriceConfidence {
+        stddev: Price::new(stddev, decimals),
+        min: Price::new(prices.iter().copied().min().unwrap_or(0), decimals),
+        max: Price::new(prices.iter().copied().max().unwrap_or(0), decimals),
+        dispersion: if price > 0 {
+            stddev as f64 / price as f64
+        } else {
+            0.0
+        },
+        sources: prices.len() as u32,
+    }
+}
+
+/// Flags the prices that deviate too far from their median.
+///
+/// Deviations are measured in scaled MADs or standard deviations depending on
+/// `method`; anything within `min_deviation` (relative to the median) is always
+/// kept, so tightly clustered rounds don't reject on noise. Fewer than three
+/// prices can't establish a consensus and are never flagged.
+///
+/// Each price's standard deviation is taken over the other prices: counting a
+/// price in its own spread would cap its score at `sqrt(n)`, so small rounds
+/// could never reject anything.
+pub fn find_outliers(
+    prices: &[u64],
+    method: OutlierMethod,
+    threshold: f64,
+    min_deviation: f64,
+) -> Vec<bool> {
+    let mut outliers = vec![false; prices.len()];
+    if prices.len() < 3 || method == OutlierMethod::None {
+        return outliers;
+    }
+    let Some(center) = median(prices) else {
+        return outliers;
+    };
+    let deviations: Vec<u64> = prices.iter().map(|price| price.abs_diff(center)).collect();
+    let mad = median(&deviations).unwrap_or(0) as f64 * MAD_SCALE;
+
+    for (i, outlier) in outliers.iter_mut().enumerate() {
+        let deviation = deviations[i] as f64;
+        if deviation <= min_deviation * center as f64 {
+            continue;
+        }
+        let spread = match method {
+            OutlierMethod::None => return outliers,
+            OutlierMethod::Mad => mad,
+            OutlierMethod::ZScore => {
+                let others = deviations
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, &other)| (other as f64).powi(2))
+                    .sum::<f64>();
+                (others / (prices.len() - 1) as f64).sqrt()
+            }
+        };
+        *outlier = spread == 0.0 || deviation / spread > threshold;
+    }
+    outliers
+}
+
+/// Weighted mean of `(mantissa, weight)` samples, e.g. volume for VWAP.
+///
+/// Weights are normalized to the largest one and quantized to integers before
+/// averaging. Samples without a positive weight are ignored. Returns `None`
+/// when the total weight is zero, so callers can fall back to an unweighted price.
+pub fn weighted_mean(samples: impl IntoIterator<Item = (u64, f64)>) -> Option<u64> {
+    let samples: Vec<(u64, f64)> = samples
+        .into_iter()
+        .filter(|(_, weight)| weight.is_finite() && *weight > 0.0)
+        .collect();
+    let max_weight = samples.iter().map(|(_, weight)| *weight).fold(0.0, f64::max);
+    if max_weight == 0.0 {
+        return None;
+    }
+
+    let (weighted_sum, total_weight) = samples.iter().fold(
+        (0u128, 0u128),
+        |(weighted_sum, total_weight), &(mantissa, weight)| {
+            let units = (weight / max_weight * WEIGHT_UNITS).round() as u128;
+            (
+                weighted_sum + u128::from(mantissa) * units,
+                total_weight + units,
+            )
+        },
+    );
+    (total_weight > 0).then(|| div_round(weighted_sum, total_weight) as u64)
+}
+
+/// Rolling VWAP over the published aggregates within `(now - window, now]`,
+/// expressed with `decimals` decimals
+pub fn rolling_volume_weighted_average(
+    points: &[PricePoint],
+    now: u64,
+    window: u64,
+    decimals: u8,
+) -> Option<Price> {
+    let start = now.saturating_sub(window);
+    let mantissa = weighted_mean(
+        points
+            .iter()
+            .filter(|point| point.timestamp > start && point.timestamp <= now)
+            .filter_map(|point| Some((point.vwap.rescale(decimals)?.mantissa, point.volume))),
+    )?;
+    Some(Price::new(mantissa, decimals))
+}
+
+/// Time-weighted average of a step series of prices over `[now - window, now]`,
+/// expressed with `decimals` decimals.
+///
+/// Each point holds its price until the next point (or `now`). `points` must be
+/// sorted by timestamp; the last point at or before the window start is used
+/// for the beginning of the window. If the series starts inside the window,
+/// the average covers only the observed part. Returns `None` for an empty series.
+pub fn time_weighted_average(
+    points: &[PricePoint],
+    now: u64,
+    window: u64,
+    decimals: u8,
+) -> Option<Price> {
+    let start = now.saturating_sub(window);
+    let first_inside = points.partition_point(|point| point.timestamp <= start);
+    let relevant = &points[first_inside.saturating_sub(1)..];
+    let last = relevant.last()?;
+
+    let mut weighted_sum = 0u128;
+    let mut elapsed = 0u128;
+    for (i, point) in relevant.iter().enumerate() {
+        let from = point.timestamp.max(start);
+        let to = relevant
+            .get(i + 1)
+            .map_or(now, |next| next.timestamp)
+            .min(now);
+        if to <= from {
+            continue;
+        }
+        let Some(price) = point.price.rescale(decimals) else {
+            continue;
+        };
+        let duration = u128::from(to - from);
+        weighted_sum += u128::from(price.mantissa) * duration;
+        elapsed += duration;
+    }
+
+    if elapsed == 0 {
+        return last.price.rescale(decimals);
+    }
+    Some(Price::new(div_round(weighted_sum, elapsed) as u64, decimals))
+}
+
+/// Candle starting at `bucket_start` covering `candles`, which must be sorted
+/// oldest first. Returns `None` for no candles.
+pub fn roll_up(candles: &[Candle], bucket_start: u64) -> Option<Candle> {
+    let (first, last) = (candles.first()?, candles.last()?);
+    Some(Candle {
+        open: first.open,
+        high: candles.iter().map(|candle| candle.high).max()?,
+        low: candles.iter().map(|candle| candle.low).min()?,
+        close: last.close,
+        volume: candles.iter().map(|candle| candle.volume).sum(),
+        timestamp: bucket_start,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(timestamp: u64, mantissa: u64) -> PricePoint {
+        PricePoint {
+            price: Price::new(mantissa, 2),
+            vwap: Price::new(mantissa, 2),
+            volume: 0.0,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn median_rounds_the_mean_of_the_middle_values_for_even_counts() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[3, 1, 2]), Some(2));
+        assert_eq!(median(&[40, 10, 30, 20]), Some(25));
+        assert_eq!(median(&[1, 2]), Some(2));
+        assert_eq!(median(&[u64::MAX, u64::MAX]), Some(u64::MAX));
+    }
+
+    #[test]
+    fn mean_rounds_half_up() {
+        assert_eq!(mean(&[]), None);
+        assert_eq!(mean(&[1, 2]), Some(2));
+        assert_eq!(mean(&[1, 1, 2]), Some(1));
+    }
+
+    #[test]
+    fn weighted_mean_quantizes_weights_relative_to_the_largest() {
+        assert_eq!(weighted_mean([(100, 1.0), (200, 1.0)]), Some(150));
+        assert_eq!(weighted_mean([(100, 1.0), (200, 3.0)]), Some(175));
+        // Scaling every weight doesn't change the result
+        assert_eq!(weighted_mean([(100, 1e-6), (200, 3e-6)]), Some(175));
+        // A weight below the quantum rounds to nothing
+        assert_eq!(weighted_mean([(100, 1.0), (1_000_000, 1e-12)]), Some(100));
+    }
+
+    #[test]
+    fn weighted_mean_ignores_unusable_weights() {
+        assert_eq!(weighted_mean([(100, 0.0), (200, f64::NAN), (300, -1.0), (400, 2.0)]), Some(400));
+        assert_eq!(weighted_mean([(100, 0.0)]), None);
+        assert_eq!(weighted_mean(Vec::new()), None);
+    }
+
+    #[test]
+    fn time_weighted_average_holds_each_price_until_the_next() {
+        let points = [point(0, 100), point(50, 200)];
+        let twap = |window| time_weighted_average(&points, 100, window, 2).map(|price| price.mantissa);
+        assert_eq!(twap(100), Some(150));
+        assert_eq!(twap(60), Some(183));
+        // A point at the window start is the price in effect there
+        assert_eq!(twap(50), Some(200));
+        assert_eq!(twap(40), Some(200));
+    }
+
+    #[test]
+    fn time_weighted_average_covers_only_the_observed_part_of_the_window() {
+        let points = [point(80, 100)];
+        assert_eq!(time_weighted_average(&points, 100, 100, 2).map(|price| price.mantissa), Some(100));
+        assert_eq!(time_weighted_average(&[], 100, 100, 2), None);
+    }
+
+    #[test]
+    fn time_weighted_average_of_a_point_at_now_is_that_point() {
+        let points = [point(100, 300)];
+        assert_eq!(time_weighted_average(&points, 100, 100, 2).map(|price| price.mantissa), Some(300));
+    }
+
+    #[test]
+    fn time_weighted_average_rescales_to_the_requested_decimals() {
+        let points = [point(0, 12_345)];
+        let twap = time_weighted_average(&points, 100, 100, 1).unwrap();
+        assert_eq!((twap.mantissa, twap.decimals), (1_235, 1));
+    }
+
+    #[test]
+    fn find_outliers_needs_three_prices() {
+        assert_eq!(find_outliers(&[100, 1_000], OutlierMethod::Mad, 3.0, 0.0), [false, false]);
+        assert_eq!(find_outliers(&[100, 100, 1_000], OutlierMethod::None, 3.0, 0.0), [false; 3]);
+    }
+
+    #[test]
+    fn find_outliers_rejects_by_mad() {
+        let outliers = find_outliers(&[100, 101, 102, 150], OutlierMethod::Mad, 3.0, 0.0);
+        assert_eq!(outliers, [false, false, false, true]);
+    }
+
+    #[test]
+    fn find_outliers_rejects_by_z_score_in_small_rounds() {
+        let outliers = find_outliers(&[100, 100, 130], OutlierMethod::ZScore, 3.0, 0.01);
+        assert_eq!(outliers, [false, false, true]);
+        let outliers = find_outliers(&[1_000, 1_001, 1_002, 1_300], OutlierMethod::ZScore, 3.0, 0.0);
+        assert_eq!(outliers, [false, false, false, true]);
+        let outliers = find_outliers(&[90, 100, 110], OutlierMethod::ZScore, 3.0, 0.0);
+        assert_eq!(outliers, [false; 3]);
+    }
+
+    #[test]
+    fn find_outliers_keeps_prices_within_the_minimum_deviation() {
+        let outliers = find_outliers(&[1_000, 1_000, 1_009], OutlierMethod::ZScore, 3.0, 0.01);
+        assert_eq!(outliers, [false; 3]);
+        let outliers = find_outliers(&[1_000, 1_000, 1_009], OutlierMethod::Mad, 3.0, 0.01);
+        assert_eq!(outliers, [false; 3]);
+    }
+}
@@ -4,14 +4,22 @@ mod state;
 
 use linera_sdk::{
     abi::WithContractAbi,
-    linera_base_types::{ChainId, StreamUpdate},
+    linera_base_types::ChainId,
     Contract,
     ContractRuntime,
     views::{RootView, View},
 };
 use oracle_microchain::{
-    AlertConfig, Candle, CandleInterval, OracleAbi, OracleEvent, OracleInput, OracleMessage,
-    OracleParameters, OracleReputation, Operation, PriceData, ThresholdType, ORACLE_STREAM_NAME,
+    aggregation::{
+        find_outliers, mean, median, price_confidence, roll_up, rolling_volume_weighted_average,
+        time_weighted_average, weighted_mean,
+    },
+    accuracy_score, candle_key, normalize_symbol, AlertConfig, Asset, Candle, CandleInterval, DuplicatePolicy,
+    OracleAbi, OracleError, OracleEvent, OracleEventRecord, OracleInput, OracleMessage, OracleParameters,
+    OracleReputation, OracleResponse, OracleRound, Operation, Price, PriceData, PricePoint, ProviderRecord,
+    ProviderStatus, ReputationPolicy, ReputationSample, RoundStatus, ThresholdType, TokenConfig,
+    DEFAULT_PROVIDER_GRACE_SECS, MAX_EVENT_LOG_LEN, MAX_REPUTATION_HISTORY_LEN, MAX_ROUND_HISTORY_LEN,
+    MAX_TWAP_WINDOW_SECS, MICROS_PER_SECOND, ORACLE_STREAM_NAME, REPUTATION_SMOOTHING,
 };
 
 use self::state::OracleState;
@@ -42,262 +50,1293 @@ impl Contract for OracleContract {
 
     async fn instantiate(&mut self, _argument: ()) {
         self.runtime.application_parameters();
+        // A new application has no legacy state to migrate
+        self.state.migrated.set(true);
     }
 
     async fn execute_operation(&mut self, operation: Operation) -> Self::Response {
+        self.migrate_legacy_state().await;
+
         match operation {
             Operation::SubmitPrice {
                 token,
                 price,
                 source,
                 timestamp,
+                volume,
             } => {
                 log::info!("Operation::SubmitPrice - token: {}, price: {}, source: {}", token, price, source);
-                self.handle_submit_price(token, price, source, timestamp).await;
+                let chain_id = self.runtime.chain_id();
+                self.authorize_source(&source, chain_id).await?;
+                let params = self.runtime.application_parameters();
+                if chain_id == params.aggregator_chain {
+                    return self.handle_submit_price(token, price, source, timestamp, volume).await;
+                }
+                // Unusable prices are forwarded too, so the aggregator counts them against the source
+                self.send_message(
+                    params.aggregator_chain,
+                    OracleMessage::SubmitPrice {
+                        token,
+                        price,
+                        source,
+                        timestamp,
+                        volume,
+                    },
+                );
+                Ok(OracleResponse::Forwarded)
             }
             Operation::SubscribeTo { chain_id } => {
                 log::info!("Operation::SubscribeTo - chain_id: {:?}", chain_id);
                 let app_id = self.runtime.application_id().forget_abi();
                 self.runtime.subscribe_to_events(chain_id, app_id, ORACLE_STREAM_NAME.into());
+                Ok(OracleResponse::Ok)
             }
             Operation::UnsubscribeFrom { chain_id } => {
                 log::info!("Operation::UnsubscribeFrom - chain_id: {:?}", chain_id);
                 let app_id = self.runtime.application_id().forget_abi();
                 self.runtime.unsubscribe_from_events(chain_id, app_id, ORACLE_STREAM_NAME.into());
+                Ok(OracleResponse::Ok)
             }
             Operation::RegisterProvider {
                 provider_chain,
                 source_name,
             } => {
                 log::info!("Operation::RegisterProvider - source: {}, chain: {:?}", source_name, provider_chain);
-                self.handle_register_provider(provider_chain, source_name).await;
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                self.handle_register_provider(provider_chain, source_name.clone()).await?;
+                self.forward_to_provider_chains(
+                    &[provider_chain],
+                    OracleMessage::RegisterProvider {
+                        provider_chain,
+                        source_name,
+                    },
+                );
+                Ok(OracleResponse::Ok)
+            }
+            Operation::DeregisterProvider { source_name } => {
+                log::info!("Operation::DeregisterProvider - source: {}", source_name);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                let record = self.handle_deregister_provider(&source_name).await?;
+                self.forward_to_provider_chains(
+                    &record.chains(),
+                    OracleMessage::DeregisterProvider { source_name },
+                );
+                Ok(OracleResponse::Ok)
+            }
+            Operation::SuspendProvider { source_name } => {
+                log::info!("Operation::SuspendProvider - source: {}", source_name);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                let record = self.handle_suspend_provider(&source_name).await?;
+                self.forward_to_provider_chains(
+                    &record.chains(),
+                    OracleMessage::SuspendProvider { source_name },
+                );
+                Ok(OracleResponse::Ok)
+            }
+            Operation::ResumeProvider { source_name } => {
+                log::info!("Operation::ResumeProvider - source: {}", source_name);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                let record = self.handle_resume_provider(&source_name).await?;
+                self.forward_to_provider_chains(
+                    &record.chains(),
+                    OracleMessage::ResumeProvider { source_name },
+                );
+                Ok(OracleResponse::Ok)
+            }
+            Operation::RotateProvider {
+                source_name,
+                new_chain,
+                grace_period_secs,
+            } => {
+                log::info!("Operation::RotateProvider - source: {}, chain: {:?}", source_name, new_chain);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                let known = self
+                    .state
+                    .provider_record(&source_name)
+                    .await
+                    .expect("Failed to get provider");
+                if known.is_none() {
+                    return Err(OracleError::UnknownProvider(source_name));
+                }
+                let grace_period_secs = grace_period_secs.unwrap_or(DEFAULT_PROVIDER_GRACE_SECS);
+                let grace_until = self
+                    .runtime
+                    .system_time()
+                    .micros()
+                    .saturating_add(grace_period_secs.saturating_mul(MICROS_PER_SECOND));
+                let record = self
+                    .handle_rotate_provider(&source_name, new_chain, grace_until)
+                    .await;
+                self.forward_to_provider_chains(
+                    &record.chains(),
+                    OracleMessage::RotateProvider {
+                        source_name,
+                        new_chain,
+                        grace_until,
+                    },
+                );
+                Ok(OracleResponse::Ok)
+            }
+            Operation::ConfigureReputation { policy } => {
+                log::info!("Operation::ConfigureReputation");
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                self.handle_configure_reputation(policy.clone())?;
+                let params = self.runtime.application_parameters();
+                if params.aggregator_chain != params.master_chain {
+                    self.send_message(
+                        params.aggregator_chain,
+                        OracleMessage::ConfigureReputation { policy },
+                    );
+                }
+                Ok(OracleResponse::Ok)
             }
             Operation::UpdateCandle {
                 token,
                 interval,
                 candle,
             } => {
-                self.handle_candle_update(token, interval, candle).await;
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                self.handle_candle_update(token.clone(), interval, candle.clone())
+                    .await?;
+                let params = self.runtime.application_parameters();
+                if params.aggregator_chain != params.master_chain {
+                    self.send_message(
+                        params.aggregator_chain,
+                        OracleMessage::UpdateCandle {
+                            token,
+                            interval,
+                            candle,
+                        },
+                    );
+                }
+                Ok(OracleResponse::Ok)
             }
             Operation::SetAlert { user_id, alert } => {
-                self.handle_set_alert(user_id, alert).await;
+                self.handle_set_alert(user_id, alert).await?;
+                Ok(OracleResponse::Ok)
             }
             Operation::RemoveAlert { user_id, alert_id } => {
-                self.handle_remove_alert(user_id, alert_id).await;
+                self.handle_remove_alert(user_id, alert_id).await?;
+                Ok(OracleResponse::Ok)
             }
             Operation::RequestAggregation { token } => {
                 log::info!("Operation::RequestAggregation - token: {}", token);
                 self.handle_request_aggregation(token).await;
+                Ok(OracleResponse::Ok)
+            }
+            Operation::RegisterAsset {
+                symbol,
+                base,
+                quote,
+                display_name,
+                config,
+            } => {
+                log::info!("Operation::RegisterAsset - symbol: {}", symbol);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                let symbol = normalize_symbol(&symbol);
+                let previous = self
+                    .state
+                    .assets
+                    .get(&symbol)
+                    .await
+                    .expect("Failed to get asset");
+                let config = match config {
+                    Some(config) => config,
+                    None => self
+                        .state
+                        .token_config(&symbol)
+                        .await
+                        .expect("Failed to get token config"),
+                };
+                let asset = Asset {
+                    symbol,
+                    base: normalize_symbol(&base),
+                    quote: normalize_symbol(&quote),
+                    display_name,
+                    enabled: previous.as_ref().is_none_or(|previous| previous.enabled),
+                    config,
+                    providers: previous.and_then(|previous| previous.providers),
+                };
+                self.handle_register_asset(asset.clone()).await?;
+                self.forward_asset(asset);
+                Ok(OracleResponse::Ok)
+            }
+            Operation::SetTokenProviders { token, sources } => {
+                log::info!("Operation::SetTokenProviders - token: {}, sources: {:?}", token, sources);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                let Some(mut asset) = self.state.asset(&token).await.expect("Failed to get asset") else {
+                    return Err(OracleError::UnknownToken(token));
+                };
+                if let Some(sources) = &sources {
+                    if sources.is_empty() {
+                        return Err(OracleError::InvalidAsset(
+                            "provider list must not be empty".to_string(),
+                        ));
+                    }
+                    for source in sources {
+                        self.known_provider(source).await?;
+                    }
+                }
+                asset.providers = sources.map(|mut sources| {
+                    sources.sort();
+                    sources.dedup();
+                    sources
+                });
+                self.handle_register_asset(asset.clone()).await?;
+                self.forward_asset(asset);
+                Ok(OracleResponse::Ok)
+            }
+            Operation::SetAssetEnabled { symbol, enabled } => {
+                log::info!("Operation::SetAssetEnabled - symbol: {}, enabled: {}", symbol, enabled);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                let Some(mut asset) = self.state.asset(&symbol).await.expect("Failed to get asset") else {
+                    return Err(OracleError::UnknownToken(symbol));
+                };
+                asset.enabled = enabled;
+                self.handle_register_asset(asset.clone()).await?;
+                self.forward_asset(asset);
+                Ok(OracleResponse::Ok)
+            }
+            Operation::ConfigureToken { token, config } => {
+                log::info!("Operation::ConfigureToken - token: {}", token);
+                let chain_id = self.runtime.chain_id();
+                self.ensure_master_chain(chain_id)?;
+                self.handle_configure_token(token.clone(), config.clone()).await?;
+                let params = self.runtime.application_parameters();
+                if params.aggregator_chain != params.master_chain {
+                    self.send_message(
+                        params.aggregator_chain,
+                        OracleMessage::ConfigureToken { token, config },
+                    );
+                }
+                Ok(OracleResponse::Ok)
+            }
+            Operation::CloseRound { token } => {
+                log::info!("Operation::CloseRound - token: {}", token);
+                self.handle_close_round(token).await
             }
         }
     }
 
     async fn execute_message(&mut self, message: OracleMessage) {
+        self.migrate_legacy_state().await;
+
         let origin_chain = self.runtime.message_origin_chain_id().expect("Missing origin chain");
-        
+
+        // Rejected messages are dropped rather than failing the block, so a
+        // bad message can't block the inbox
+        if let Err(error) = self.handle_message(origin_chain, message).await {
+            log::warn!("Rejected message from {:?}: {}", origin_chain, error);
+        }
+    }
+
+    async fn store(mut self) {
+        self.state.save().await.expect("Failed to save state");
+    }
+}
+
+impl OracleContract {
+    /// Move the prices, alerts and reputations stored before prices were
+    /// fixed-point into their current views, once. Submissions pending in the
+    /// legacy layout are dropped: providers resubmit into a new round.
+    async fn migrate_legacy_state(&mut self) {
+        if *self.state.migrated.get() {
+            return;
+        }
+        self.state.migrated.set(true);
+
+        let mut prices = Vec::new();
+        self.state
+            .legacy_prices
+            .for_each_index_value(|token, price_data| {
+                prices.push((token, price_data.into_owned()));
+                Ok(())
+            })
+            .await
+            .expect("Failed to read legacy prices");
+        // Mark the tokens updated in publication order, so the latest price stays the latest
+        prices.sort_by_key(|(_, price_data)| price_data.timestamp);
+        log::info!("Migrating {} legacy prices", prices.len());
+        for (token, legacy) in prices {
+            let symbol = normalize_symbol(&token);
+            let config = self
+                .state
+                .token_config(&symbol)
+                .await
+                .expect("Failed to get token config");
+            let Some(price_data) = legacy.to_price_data(symbol.clone(), &config) else {
+                log::warn!("Dropping legacy price of {}: {} can't be represented", token, legacy.price);
+                continue;
+            };
+            self.state
+                .prices
+                .insert(&symbol, price_data)
+                .expect("Failed to insert price");
+            self.mark_updated(&symbol).await;
+        }
+        self.state.legacy_prices.clear();
+        self.state.latest_price.set(None);
+        self.state.pending_prices.clear();
+
+        let mut alerts = Vec::new();
+        self.state
+            .legacy_alerts
+            .for_each_index_value(|user_id, user_alerts| {
+                alerts.push((user_id, user_alerts.into_owned()));
+                Ok(())
+            })
+            .await
+            .expect("Failed to read legacy alerts");
+        for (user_id, legacy) in alerts {
+            let mut user_alerts = Vec::with_capacity(legacy.len());
+            for alert in legacy {
+                let token = normalize_symbol(&alert.token);
+                let config = self
+                    .state
+                    .token_config(&token)
+                    .await
+                    .expect("Failed to get token config");
+                match alert.to_alert(token, config.decimals) {
+                    Some(alert) => user_alerts.push(alert),
+                    None => log::warn!("Dropping alert {} of {}: threshold can't be represented", alert.id, user_id),
+                }
+            }
+            self.state
+                .alerts
+                .insert(&user_id, user_alerts)
+                .expect("Failed to insert alerts");
+        }
+        self.state.legacy_alerts.clear();
+
+        let mut reputations = Vec::new();
+        self.state
+            .legacy_oracle_stats
+            .for_each_index_value(|source, reputation| {
+                reputations.push((source, reputation.to_reputation()));
+                Ok(())
+            })
+            .await
+            .expect("Failed to read legacy reputations");
+        for (source, reputation) in reputations {
+            self.state
+                .oracle_stats
+                .insert(&source, reputation)
+                .expect("Failed to insert reputation");
+        }
+        self.state.legacy_oracle_stats.clear();
+    }
+
+    /// Apply a message from `origin_chain`
+    async fn handle_message(
+        &mut self,
+        origin_chain: ChainId,
+        message: OracleMessage,
+    ) -> Result<(), OracleError> {
         match message {
             OracleMessage::SubmitPrice {
                 token,
                 price,
                 source,
                 timestamp,
+                volume,
             } => {
                 log::info!("Message::SubmitPrice from {:?} - token: {}, price: {}", origin_chain, token, price);
-                self.handle_submit_price(token, price, source, timestamp).await;
+                self.authorize_source(&source, origin_chain).await?;
+                self.handle_submit_price(token, price, source, timestamp, volume).await?;
             }
             OracleMessage::RequestPrice { token, requester } => {
                 log::info!("Message::RequestPrice from {:?} - token: {}", origin_chain, token);
                 self.handle_price_request(token, requester).await;
             }
             OracleMessage::AggregationResult {
-                token,
-                aggregated_price,
-                median,
-                twap,
-                vwap,
+                price_data,
                 oracle_inputs,
-                timestamp,
             } => {
-                log::info!("Message::AggregationResult - token: {}, price: {}", token, aggregated_price);
-                self.handle_aggregation_result(
-                    token,
-                    aggregated_price,
-                    median,
-                    twap,
-                    vwap,
-                    oracle_inputs,
-                    timestamp,
+                log::info!("Message::AggregationResult from {:?} - token: {}, price: {}", origin_chain, price_data.token, price_data.price);
+                self.ensure_aggregator_chain(origin_chain)?;
+                let latest = self
+                    .state
+                    .prices
+                    .get(&price_data.token)
+                    .await
+                    .expect("Failed to get price");
+                if let Some(latest) = latest {
+                    if price_data.timestamp <= latest.timestamp {
+                        return Err(OracleError::StaleTimestamp {
+                            timestamp: price_data.timestamp,
+                            latest: latest.timestamp,
+                        });
+                    }
+                }
+                self.record_price_point(
+                    &price_data.token,
+                    price_data.price,
+                    price_data.round_vwap,
+                    price_data.volume,
+                    price_data.timestamp,
                 )
                 .await;
+                self.publish_price(*price_data, oracle_inputs).await;
             }
             OracleMessage::RegisterProvider {
                 provider_chain,
                 source_name,
             } => {
                 log::info!("Message::RegisterProvider from {:?} - source: {}", origin_chain, source_name);
-                let params = self.runtime.application_parameters();
-                assert_eq!(
-                    origin_chain, params.master_chain,
-                    "Only master chain can register providers"
-                );
-                self.handle_register_provider(provider_chain, source_name).await;
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_register_provider(provider_chain, source_name).await?;
+            }
+            OracleMessage::DeregisterProvider { source_name } => {
+                log::info!("Message::DeregisterProvider from {:?} - source: {}", origin_chain, source_name);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_deregister_provider(&source_name).await?;
+            }
+            OracleMessage::SuspendProvider { source_name } => {
+                log::info!("Message::SuspendProvider from {:?} - source: {}", origin_chain, source_name);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_suspend_provider(&source_name).await?;
+            }
+            OracleMessage::ResumeProvider { source_name } => {
+                log::info!("Message::ResumeProvider from {:?} - source: {}", origin_chain, source_name);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_resume_provider(&source_name).await?;
+            }
+            OracleMessage::RotateProvider {
+                source_name,
+                new_chain,
+                grace_until,
+            } => {
+                log::info!("Message::RotateProvider from {:?} - source: {}", origin_chain, source_name);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_rotate_provider(&source_name, new_chain, grace_until)
+                    .await;
+            }
+            OracleMessage::RegisterAsset { asset } => {
+                log::info!("Message::RegisterAsset from {:?} - symbol: {}", origin_chain, asset.symbol);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_register_asset(*asset).await?;
+            }
+            OracleMessage::ConfigureReputation { policy } => {
+                log::info!("Message::ConfigureReputation from {:?}", origin_chain);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_configure_reputation(policy)?;
+            }
+            OracleMessage::ConfigureToken { token, config } => {
+                log::info!("Message::ConfigureToken from {:?} - token: {}", origin_chain, token);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_configure_token(token, config).await?;
+            }
+            OracleMessage::UpdateCandle {
+                token,
+                interval,
+                candle,
+            } => {
+                log::info!("Message::UpdateCandle from {:?} - token: {}", origin_chain, token);
+                self.ensure_master_chain(origin_chain)?;
+                self.handle_candle_update(token, interval, candle).await?;
             }
         }
+        Ok(())
     }
 
-    async fn store(mut self) {
-        self.state.save().await.expect("Failed to save state");
+    /// Refuse requests from chains other than the master chain
+    fn ensure_master_chain(&mut self, chain_id: ChainId) -> Result<(), OracleError> {
+        if chain_id == self.runtime.application_parameters().master_chain {
+            Ok(())
+        } else {
+            Err(OracleError::Unauthorized(chain_id))
+        }
+    }
+
+    /// Refuse results from chains other than the aggregator chain
+    fn ensure_aggregator_chain(&mut self, chain_id: ChainId) -> Result<(), OracleError> {
+        if chain_id == self.runtime.application_parameters().aggregator_chain {
+            Ok(())
+        } else {
+            Err(OracleError::Unauthorized(chain_id))
+        }
+    }
+
+    /// Check that `source` is an active provider accepted from `chain_id`
+    async fn authorize_source(&mut self, source: &str, chain_id: ChainId) -> Result<(), OracleError> {
+        let Some(record) = self
+            .state
+            .provider_record(source)
+            .await
+            .expect("Failed to get provider")
+        else {
+            return Err(OracleError::UnknownProvider(source.to_string()));
+        };
+        if record.status == ProviderStatus::Suspended {
+            return Err(OracleError::ProviderSuspended(source.to_string()));
+        }
+        if !record.accepts(chain_id, self.runtime.system_time().micros()) {
+            return Err(OracleError::UnauthorizedSource {
+                source_name: source.to_string(),
+                expected: record.chain_id,
+                actual: chain_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Send a provider change on to the aggregator chain and the provider's
+    /// chains, which check submissions against their own registry
+    fn forward_to_provider_chains(&mut self, provider_chains: &[ChainId], message: OracleMessage) {
+        let params = self.runtime.application_parameters();
+        let mut destinations = vec![params.aggregator_chain];
+        for chain_id in provider_chains {
+            if !destinations.contains(chain_id) {
+                destinations.push(*chain_id);
+            }
+        }
+        for destination in destinations {
+            if destination != params.master_chain {
+                self.send_message(destination, message.clone());
+            }
+        }
+    }
+
+    /// Send a registered asset on to the aggregator chain
+    fn forward_asset(&mut self, asset: Asset) {
+        let params = self.runtime.application_parameters();
+        if params.aggregator_chain != params.master_chain {
+            self.send_message(
+                params.aggregator_chain,
+                OracleMessage::RegisterAsset {
+                    asset: Box::new(asset),
+                },
+            );
+        }
+    }
+
+    /// Enabled asset for a token given as its symbol or pair
+    async fn resolve_asset(&self, token: &str) -> Result<Asset, OracleError> {
+        match self.state.asset(token).await.expect("Failed to get asset") {
+            None => Err(OracleError::UnknownToken(token.to_string())),
+            Some(asset) if !asset.enabled => Err(OracleError::AssetDisabled(asset.symbol)),
+            Some(asset) => Ok(asset),
+        }
     }
-}
 
-impl OracleContract {
     /// Handle price submission from oracle provider
     async fn handle_submit_price(
         &mut self,
         token: String,
-        price: f64,
+        price: Price,
         source: String,
         timestamp: u64,
-    ) {
-        // Add to pending prices for aggregation
-        let mut pending = self
+        volume: Option<f64>,
+    ) -> Result<OracleResponse, OracleError> {
+        let asset = self.resolve_asset(&token).await?;
+        if !asset.serves(&source) {
+            return Err(OracleError::SourceNotAllowed {
+                source_name: source,
+                token: asset.symbol,
+            });
+        }
+        let Asset { symbol: token, config, .. } = asset;
+        let latest = self
             .state
-            .pending_prices
+            .prices
             .get(&token)
             .await
-            .expect("Failed to get pending prices")
-            .unwrap_or_default();
+            .expect("Failed to get price");
+        if let Some(latest) = latest {
+            if timestamp <= latest.timestamp {
+                return Err(OracleError::StaleTimestamp {
+                    timestamp,
+                    latest: latest.timestamp,
+                });
+            }
+        }
+
+        let now = self.runtime.system_time().micros();
+        let earliest = now.saturating_sub(config.max_submission_age_secs * MICROS_PER_SECOND);
+        let latest = now.saturating_add(config.max_clock_drift_secs * MICROS_PER_SECOND);
+        if timestamp < earliest || timestamp > latest {
+            return Err(OracleError::TimestampOutOfRange {
+                timestamp,
+                earliest,
+                latest,
+            });
+        }
+        let open_round = self
+            .state
+            .open_rounds
+            .get(&token)
+            .await
+            .expect("Failed to get open round");
+        if let Some(round) = open_round {
+            let expired = now >= round.expires_at(&config);
+            if !expired
+                && config.duplicate_policy == DuplicatePolicy::FirstWins
+                && round.sources.contains(&source)
+            {
+                return Err(OracleError::DuplicateSubmission {
+                    source_name: source,
+                    round_id: round.round_id,
+                });
+            }
+        }
 
-        pending.push((source.clone(), price, timestamp));
+        // Settle rounds that timed out before this submission, this token's included
+        self.close_expired_rounds(now).await;
 
-        self.state
-            .pending_prices
-            .insert(&token, pending.clone())
-            .expect("Failed to insert pending prices");
+        // Add to the token's open round, starting one if needed
+        let mut round = match self
+            .state
+            .open_rounds
+            .get(&token)
+            .await
+            .expect("Failed to get open round")
+        {
+            Some(round) => round,
+            None => {
+                let round_count = self
+                    .state
+                    .round_count(&token)
+                    .await
+                    .expect("Failed to count rounds");
+                OracleRound::new(round_count + 1, token.clone(), now)
+            }
+        };
+
+        // Treat unusable volume reports as absent rather than rejecting the price
+        let volume = volume.filter(|volume| volume.is_finite() && *volume >= 0.0);
+
+        // Unusable prices join the round as rejected, so they show in its breakdown
+        let rejected = !price.is_valid();
+        let input = OracleInput {
+            source: source.clone(),
+            price,
+            volume,
+            latency: now.saturating_sub(timestamp),
+            timestamp,
+            rejected,
+        };
+        match round.submissions.iter().position(|input| input.source == source) {
+            Some(index) => round.submissions[index] = input,
+            None => {
+                round.submissions.push(input);
+                round.sources.push(source.clone());
+            }
+        }
+        round.updated_at = now;
 
         // Update reputation
         self.update_provider_reputation(&source, timestamp).await;
+        if rejected {
+            log::warn!("Rejected invalid price {} from {} for {}", price, source, token);
+            self.record_rejection(&source).await;
+        }
 
-        // If we have enough submissions, aggregate
-        let provider_count = self.state.providers.count().await.unwrap_or(0);
-        if pending.len() >= provider_count.min(3) as usize {
-            self.aggregate_and_publish(token, pending).await;
+        // Aggregate once enough distinct sources of the token have submitted,
+        // counting neither submissions nor providers in quarantine
+        let provider_count = self.count_eligible_providers(&token).await;
+        if self.count_eligible(&round.sources).await < config.quorum(provider_count) {
+            let submissions = round.submissions.len() as u32;
+            self.state
+                .open_rounds
+                .insert(&token, round)
+                .expect("Failed to insert open round");
+            return Ok(OracleResponse::Pending { submissions });
+        }
+        Ok(self.close_round(round, RoundStatus::Finalized).await)
+    }
+
+    /// Close the token's open round if it has timed out, and the candles
+    /// whose interval has ended
+    async fn handle_close_round(&mut self, token: String) -> Result<OracleResponse, OracleError> {
+        let now = self.runtime.system_time().micros();
+        let closed_candles = self.close_ended_candles(now).await;
+        let token = self.state.symbol(&token).await.expect("Failed to get asset");
+        let round = self
+            .state
+            .open_rounds
+            .get(&token)
+            .await
+            .expect("Failed to get open round");
+        let Some(round) = round else {
+            if closed_candles > 0 {
+                return Ok(OracleResponse::Ok);
+            }
+            return Err(OracleError::NoOpenRound(token));
+        };
+        let config = self
+            .state
+            .token_config(&token)
+            .await
+            .expect("Failed to get token config");
+        let expires_at = round.expires_at(&config);
+        if now < expires_at {
+            if closed_candles > 0 {
+                return Ok(OracleResponse::Ok);
+            }
+            return Err(OracleError::RoundNotExpired {
+                round_id: round.round_id,
+                expires_at,
+            });
+        }
+        Ok(self.close_round(round, RoundStatus::TimedOut).await)
+    }
+
+    /// Close every open round that is older than its token's maximum round age
+    async fn close_expired_rounds(&mut self, now: u64) {
+        let mut open_rounds = Vec::new();
+        self.state
+            .open_rounds
+            .for_each_index_value(|_token, round| {
+                open_rounds.push(round.into_owned());
+                Ok(())
+            })
+            .await
+            .expect("Failed to read open rounds");
+
+        for round in open_rounds {
+            let config = self
+                .state
+                .token_config(&round.token)
+                .await
+                .expect("Failed to get token config");
+            if now >= round.expires_at(&config) {
+                self.close_round(round, RoundStatus::TimedOut).await;
+            }
         }
     }
 
-    /// Aggregate prices and publish event
-    async fn aggregate_and_publish(&mut self, token: String, submissions: Vec<(String, f64, u64)>) {
-        let mut prices: Vec<f64> = submissions.iter().map(|(_, p, _)| *p).collect();
-        prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    /// Aggregate a round if it has enough distinct sources, abandon it
+    /// otherwise, and move it to the token's round history
+    async fn close_round(&mut self, mut round: OracleRound, status: RoundStatus) -> OracleResponse {
+        self.state
+            .open_rounds
+            .remove(&round.token)
+            .expect("Failed to remove open round");
 
-        let median = if prices.is_empty() {
-            0.0
+        let config = self
+            .state
+            .token_config(&round.token)
+            .await
+            .expect("Failed to get token config");
+        let provider_count = self.count_eligible_providers(&round.token).await;
+        let answer = if self.count_eligible(&round.sources).await >= config.required_sources(provider_count) {
+            self.aggregate_and_publish(&mut round).await
         } else {
-            prices[prices.len() / 2]
+            None
+        };
+        round.answered_at = Some(self.runtime.system_time().micros());
+        round.answer = answer;
+        round.status = if answer.is_some() { status } else { RoundStatus::Abandoned };
+        self.score_round(&round, status == RoundStatus::TimedOut).await;
+
+        let event = match answer {
+            Some(answer) => OracleEvent::RoundFinalized {
+                token: round.token.clone(),
+                round_id: round.round_id,
+                answer,
+                sources: round.sources.clone(),
+                status: round.status,
+            },
+            None => {
+                log::warn!(
+                    "Abandoned round {} for {} with {} sources",
+                    round.round_id,
+                    round.token,
+                    round.sources.len()
+                );
+                OracleEvent::RoundAbandoned {
+                    token: round.token.clone(),
+                    round_id: round.round_id,
+                    sources: round.sources.clone(),
+                }
+            }
+        };
+        self.emit_event(event);
+
+        let response = match answer {
+            Some(price) => OracleResponse::Published { price },
+            None => OracleResponse::Abandoned {
+                round_id: round.round_id,
+            },
         };
+        let token = round.token.clone();
+        let round_id = round.round_id;
+        let rounds = self
+            .state
+            .rounds
+            .load_entry_mut(&token)
+            .await
+            .expect("Failed to load rounds");
+        rounds.insert(&round_id, round).expect("Failed to insert round");
+        if let Some(expired) = round_id.checked_sub(MAX_ROUND_HISTORY_LEN) {
+            rounds.remove(&expired).expect("Failed to remove round");
+        }
+        self.state
+            .round_counts
+            .insert(&token, round_id)
+            .expect("Failed to insert round count");
+        response
+    }
 
-        let sum: f64 = prices.iter().sum();
-        let aggregated_price = sum / prices.len() as f64;
+    /// Aggregate prices and publish event, returning the published price
+    async fn aggregate_and_publish(&mut self, round: &mut OracleRound) -> Option<Price> {
+        let token = round.token.clone();
+        let oracle_inputs = &mut round.submissions;
+        let config = self
+            .state
+            .token_config(&token)
+            .await
+            .expect("Failed to get token config");
 
-        // Simple TWAP/VWAP (in production, use time-weighted calculations)
-        let twap = aggregated_price;
-        let vwap = aggregated_price;
+        // Rescale to the token's decimals, rejecting unusable prices outright,
+        // then outliers around the median of the rest. Prices already rejected
+        // on submission were counted against their source then.
+        let decimals = config.decimals;
+        let mut uncounted: Vec<String> = oracle_inputs
+            .iter()
+            .filter(|input| input.rejected)
+            .map(|input| input.source.clone())
+            .collect();
+        for input in oracle_inputs.iter_mut() {
+            if !input.rejected {
+                match input.price.rescale(decimals).filter(Price::is_valid) {
+                    Some(price) => input.price = price,
+                    None => input.rejected = true,
+                }
+            }
+            // Quarantined sources are still scored against the answer, but
+            // neither shape it nor count as rejected
+            if self.is_quarantined(&input.source).await {
+                input.rejected = true;
+                uncounted.push(input.source.clone());
+            }
+        }
+        let valid: Vec<usize> = (0..oracle_inputs.len())
+            .filter(|&i| !oracle_inputs[i].rejected)
+            .collect();
+        let valid_prices: Vec<u64> = valid
+            .iter()
+            .map(|&i| oracle_inputs[i].price.mantissa)
+            .collect();
+        let outliers = find_outliers(
+            &valid_prices,
+            config.outlier_method,
+            config.outlier_threshold,
+            config.outlier_min_deviation,
+        );
+        for (&i, outlier) in valid.iter().zip(outliers) {
+            oracle_inputs[i].rejected = outlier;
+        }
 
-        let oracle_inputs: Vec<OracleInput> = submissions
+        let rejected_sources: Vec<String> = oracle_inputs
             .iter()
-            .map(|(source, price, timestamp)| OracleInput {
-                source: source.clone(),
-                price: *price,
-                latency: 0,
-                timestamp: *timestamp,
-            })
+            .filter(|input| input.rejected && !uncounted.contains(&input.source))
+            .map(|input| input.source.clone())
             .collect();
+        for source in &rejected_sources {
+            self.record_rejection(source).await;
+        }
+
+        let accepted: Vec<&OracleInput> = oracle_inputs.iter().filter(|input| !input.rejected).collect();
+        let prices: Vec<u64> = accepted.iter().map(|input| input.price.mantissa).collect();
+        let (Some(median), Some(mean)) = (median(&prices), mean(&prices)) else {
+            log::warn!("No valid submissions for {}: all {} rejected", token, oracle_inputs.len());
+            return None;
+        };
+
+        // Canonical price: mean weighted by each source's reputation
+        let mut weighted_prices = Vec::with_capacity(accepted.len());
+        for input in &accepted {
+            let reputation = self
+                .state
+                .oracle_stats
+                .get(&input.source)
+                .await
+                .expect("Failed to get reputation");
+            let weight = config.weighting.weight(reputation.as_ref());
+            weighted_prices.push((input.price.mantissa, weight));
+        }
+        let aggregated_mantissa = weighted_mean(weighted_prices).unwrap_or(mean);
+        let aggregated_price = Price::new(aggregated_mantissa, decimals);
+
+        // Round VWAP over the submissions that reported volume
+        let volume: f64 = accepted.iter().filter_map(|input| input.volume).sum();
+        let round_vwap = weighted_mean(
+            accepted
+                .iter()
+                .filter_map(|input| Some((input.price.mantissa, input.volume?))),
+        )
+        .map_or(aggregated_price, |mantissa| Price::new(mantissa, decimals));
 
         let timestamp = self.runtime.system_time().micros();
 
+        // TWAP and rolling VWAP over the token's published history, including this aggregate
+        self.record_price_point(&token, aggregated_price, round_vwap, volume, timestamp)
+            .await;
+        let windows = [config.twap_window_secs, config.vwap_window_secs];
+        let points = self
+            .price_points_for_windows(&token, timestamp, &windows)
+            .await;
+        let twap = time_weighted_average(
+            &points,
+            timestamp,
+            config.twap_window_secs * MICROS_PER_SECOND,
+            decimals,
+        )
+        .unwrap_or(aggregated_price);
+        let vwap = rolling_volume_weighted_average(
+            &points,
+            timestamp,
+            config.vwap_window_secs * MICROS_PER_SECOND,
+            decimals,
+        )
+        .unwrap_or(round_vwap);
+
+        self.update_candles(&token, aggregated_price, volume, timestamp)
+            .await;
+
         let price_data = PriceData {
             token: token.clone(),
             price: aggregated_price,
             timestamp,
-            source: format!("{} oracles", oracle_inputs.len()),
+            source: format!("{} oracles", accepted.len()),
             network: "Multi-Oracle".to_string(),
-            median,
+            median: Price::new(median, decimals),
             twap,
+            twap_window_secs: config.twap_window_secs,
             vwap,
+            vwap_window_secs: config.vwap_window_secs,
+            round_vwap,
+            volume,
+            mean: Price::new(mean, decimals),
+            weighting: config.weighting,
+            confidence: price_confidence(&prices, aggregated_mantissa, decimals),
+            round_id: round.round_id,
         };
 
+        let source_count = accepted.len();
+        self.publish_price(price_data, oracle_inputs.clone()).await;
+
+        // Increment query counter
+        let current_queries = *self.state.total_queries.get();
+        self.state.total_queries.set(current_queries + 1);
+
+        log::info!(
+            "Aggregated price for {}: {} from {} sources ({} rejected)",
+            token,
+            aggregated_price,
+            source_count,
+            rejected_sources.len()
+        );
+        Some(aggregated_price)
+    }
+
+    /// Store a price as the token's latest, stream it to subscribers and check alerts
+    async fn publish_price(&mut self, price_data: PriceData, oracle_inputs: Vec<OracleInput>) {
+        let token = price_data.token.clone();
+
         // Store in prices map
         self.state
             .prices
             .insert(&token, price_data.clone())
             .expect("Failed to insert price");
 
-        // Update latest price
-        self.state.latest_price.set(Some(price_data.clone()));
-
-        // Clear pending prices
-        self.state
-            .pending_prices
-            .remove(&token)
-            .expect("Failed to clear pending prices");
+        self.mark_updated(&token).await;
+        self.record_history(&price_data).await;
 
         // Emit event for subscribers
-        self.runtime.emit(
-            ORACLE_STREAM_NAME.into(),
-            &OracleEvent::PriceUpdate {
-                token: token.clone(),
-                price: aggregated_price,
-                aggregated_price,
-                median,
-                twap,
-                vwap,
-                oracle_breakdown: oracle_inputs.clone(),
-                timestamp,
-            },
-        );
+        self.emit_event(OracleEvent::PriceUpdate {
+            token: token.clone(),
+            price: price_data.price,
+            aggregated_price: price_data.price,
+            median: price_data.median,
+            twap: price_data.twap,
+            twap_window_secs: price_data.twap_window_secs,
+            vwap: price_data.vwap,
+            vwap_window_secs: price_data.vwap_window_secs,
+            round_vwap: price_data.round_vwap,
+            volume: price_data.volume,
+            mean: price_data.mean,
+            weighting: price_data.weighting,
+            confidence: price_data.confidence.clone(),
+            oracle_breakdown: oracle_inputs,
+            round_id: price_data.round_id,
+            timestamp: price_data.timestamp,
+        });
 
         // Check alerts
-        self.check_alerts(&token, aggregated_price).await;
-
-        // Increment query counter
-        let current_queries = self.state.total_queries.get().clone();
-        self.state.total_queries.set(current_queries + 1);
-
-        log::info!("Aggregated price for {}: {} from {} sources", token, aggregated_price, submissions.len());
+        self.check_alerts(&token, price_data.price).await;
     }
 
     /// Register oracle provider
-    async fn handle_register_provider(&mut self, provider_chain: ChainId, source_name: String) {
+    async fn handle_register_provider(
+        &mut self,
+        provider_chain: ChainId,
+        source_name: String,
+    ) -> Result<(), OracleError> {
+        let registered = self
+            .state
+            .provider_record(&source_name)
+            .await
+            .expect("Failed to get provider");
+        match registered {
+            Some(record) if record.chain_id == provider_chain => return Ok(()),
+            Some(record) => {
+                return Err(OracleError::ProviderExists {
+                    source_name,
+                    chain_id: record.chain_id,
+                })
+            }
+            None => {}
+        }
+
         self.state
             .providers
             .insert(&source_name, provider_chain)
             .expect("Failed to register provider");
+        self.state
+            .provider_records
+            .insert(&source_name, ProviderRecord::new(source_name.clone(), provider_chain))
+            .expect("Failed to register provider");
 
-        self.runtime.emit(
-            ORACLE_STREAM_NAME.into(),
-            &OracleEvent::ProviderRegistered {
-                source: source_name.clone(),
-                chain_id: provider_chain,
-            },
-        );
+        self.emit_event(OracleEvent::ProviderRegistered {
+            source: source_name.clone(),
+            chain_id: provider_chain,
+        });
 
         log::info!("Registered provider: {} at chain {:?}", source_name, provider_chain);
+        Ok(())
     }
 
-    /// Handle price request from consumer
-    async fn handle_price_request(&mut self, token: String, requester: ChainId) {
-        // Request prices from all providers
-        let mut provider_chains = Vec::new();
-        let _ = self
-            .state
+    /// A registered provider, or an error naming the unknown source
+    async fn known_provider(&self, source_name: &str) -> Result<ProviderRecord, OracleError> {
+        self.state
+            .provider_record(source_name)
+            .await
+            .expect("Failed to get provider")
+            .ok_or_else(|| OracleError::UnknownProvider(source_name.to_string()))
+    }
+
+    /// Remove a provider and its pending submissions, returning its last record
+    async fn handle_deregister_provider(&mut self, source_name: &str) -> Result<ProviderRecord, OracleError> {
+        let record = self.known_provider(source_name).await?;
+        self.remove_from_token_providers(source_name).await?;
+        self.state
             .providers
-            .for_each_index_value(|_source, chain_id| {
-                provider_chains.push(chain_id.into_owned());
+            .remove(source_name)
+            .expect("Failed to remove provider");
+        self.state
+            .provider_records
+            .remove(source_name)
+            .expect("Failed to remove provider");
+        self.drop_pending_submissions(source_name).await;
+
+        self.emit_event(OracleEvent::ProviderDeregistered {
+            source: source_name.to_string(),
+            chain_id: record.chain_id,
+        });
+        log::info!("Deregistered provider: {}", source_name);
+        Ok(record)
+    }
+
+    /// Refuse a provider's submissions and drop its pending ones
+    async fn handle_suspend_provider(&mut self, source_name: &str) -> Result<ProviderRecord, OracleError> {
+        let mut record = self.known_provider(source_name).await?;
+        if record.status == ProviderStatus::Suspended {
+            return Ok(record);
+        }
+        record.status = ProviderStatus::Suspended;
+        // Only active providers count towards quorums and receive price requests
+        self.state
+            .providers
+            .remove(source_name)
+            .expect("Failed to remove provider");
+        self.state
+            .provider_records
+            .insert(source_name, record.clone())
+            .expect("Failed to update provider");
+        self.drop_pending_submissions(source_name).await;
+
+        self.emit_event(OracleEvent::ProviderSuspended {
+            source: source_name.to_string(),
+            chain_id: record.chain_id,
+        });
+        log::info!("Suspended provider: {}", source_name);
+        Ok(record)
+    }
+
+    /// Accept a suspended provider's submissions again
+    async fn handle_resume_provider(&mut self, source_name: &str) -> Result<ProviderRecord, OracleError> {
+        let mut record = self.known_provider(source_name).await?;
+        if record.status == ProviderStatus::Active {
+            return Ok(record);
+        }
+        record.status = ProviderStatus::Active;
+        self.state
+            .providers
+            .insert(source_name, record.chain_id)
+            .expect("Failed to register provider");
+        self.state
+            .provider_records
+            .insert(source_name, record.clone())
+            .expect("Failed to update provider");
+
+        self.emit_event(OracleEvent::ProviderResumed {
+            source: source_name.to_string(),
+            chain_id: record.chain_id,
+        });
+        log::info!("Resumed provider: {}", source_name);
+        Ok(record)
+    }
+
+    /// Move a provider to `new_chain`, accepting its previous chain until
+    /// `grace_until`. Chains that didn't know the provider, like the new one,
+    /// start tracking it.
+    async fn handle_rotate_provider(
+        &mut self,
+        source_name: &str,
+        new_chain: ChainId,
+        grace_until: u64,
+    ) -> ProviderRecord {
+        let previous = self
+            .state
+            .provider_record(source_name)
+            .await
+            .expect("Failed to get provider");
+        let mut record = match previous {
+            Some(record) if record.chain_id == new_chain => return record,
+            Some(record) => record,
+            None => ProviderRecord::new(source_name.to_string(), new_chain),
+        };
+        if record.chain_id != new_chain {
+            record.previous_chain = Some(record.chain_id);
+            record.grace_until = grace_until;
+            record.chain_id = new_chain;
+        }
+        if record.status == ProviderStatus::Active {
+            self.state
+                .providers
+                .insert(source_name, new_chain)
+                .expect("Failed to register provider");
+        }
+        self.state
+            .provider_records
+            .insert(source_name, record.clone())
+            .expect("Failed to update provider");
+
+        self.emit_event(OracleEvent::ProviderRotated {
+            source: source_name.to_string(),
+            previous_chain: record.previous_chain,
+            chain_id: new_chain,
+            grace_until: record.grace_until,
+        });
+        log::info!("Rotated provider: {} to chain {:?}", source_name, new_chain);
+        record
+    }
+
+    /// Drop a removed provider from the tokens restricted to a set of
+    /// providers, refusing to leave one of them without any
+    async fn remove_from_token_providers(&mut self, source_name: &str) -> Result<(), OracleError> {
+        let mut restricted: Vec<Asset> = Vec::new();
+        self.state
+            .assets
+            .for_each_index_value(|_symbol, asset| {
+                if asset.providers.is_some() && asset.serves(source_name) {
+                    restricted.push(asset.into_owned());
+                }
                 Ok(())
             })
-            .await;
+            .await
+            .expect("Failed to get assets");
+        if let Some(asset) = restricted
+            .iter()
+            .find(|asset| asset.providers.as_ref().is_some_and(|providers| providers.len() == 1))
+        {
+            return Err(OracleError::LastTokenProvider {
+                source_name: source_name.to_string(),
+                token: asset.symbol.clone(),
+            });
+        }
+        for mut asset in restricted {
+            if let Some(providers) = &mut asset.providers {
+                providers.retain(|provider| provider != source_name);
+            }
+            self.state
+                .assets
+                .insert(&asset.symbol.clone(), asset)
+                .expect("Failed to insert asset");
+        }
+        Ok(())
+    }
 
-        for provider_chain in provider_chains {
+    /// Drop a provider's submissions from the open rounds, closing none: the
+    /// rounds keep waiting for the remaining providers
+    async fn drop_pending_submissions(&mut self, source_name: &str) {
+        let tokens = self
+            .state
+            .open_rounds
+            .indices()
+            .await
+            .expect("Failed to get open rounds");
+        for token in tokens {
+            let Some(mut round) = self
+                .state
+                .open_rounds
+                .get(&token)
+                .await
+                .expect("Failed to get open round")
+            else {
+                continue;
+            };
+            if !round.sources.iter().any(|source| source == source_name) {
+                continue;
+            }
+            round.sources.retain(|source| source != source_name);
+            round.submissions.retain(|input| input.source != source_name);
+            if round.submissions.is_empty() {
+                self.state
+                    .open_rounds
+                    .remove(&token)
+                    .expect("Failed to remove open round");
+            } else {
+                self.state
+                    .open_rounds
+                    .insert(&token, round)
+                    .expect("Failed to insert open round");
+            }
+        }
+    }
+
+    /// Handle price request from consumer
+    async fn handle_price_request(&mut self, token: String, requester: ChainId) {
+        // Only the aggregator chain fans requests out; a provider chain that
+        // receives one answers it off-chain with a price submission
+        let chain_id = self.runtime.chain_id();
+        if chain_id != self.runtime.application_parameters().aggregator_chain {
+            return;
+        }
+
+        // Request prices from the token's providers
+        let providers = self
+            .state
+            .token_providers(&token)
+            .await
+            .expect("Failed to get token providers");
+
+        for (_source, provider_chain) in providers {
+            if provider_chain == chain_id {
+                continue;
+            }
             self.send_message(
                 provider_chain,
                 OracleMessage::RequestPrice {
@@ -308,62 +1347,401 @@ impl OracleContract {
         }
     }
 
-    /// Handle aggregation result
-    async fn handle_aggregation_result(
+    /// Request aggregation from providers
+    async fn handle_request_aggregation(&mut self, token: String) {
+        let requester = self.runtime.chain_id();
+        let params = self.runtime.application_parameters();
+        
+        self.send_message(
+            params.aggregator_chain,
+            OracleMessage::RequestPrice { token, requester },
+        );
+    }
+
+    /// Store per-token aggregation settings
+    async fn handle_configure_token(
         &mut self,
         token: String,
-        aggregated_price: f64,
-        median: f64,
-        twap: f64,
-        vwap: f64,
-        oracle_inputs: Vec<OracleInput>,
+        config: TokenConfig,
+    ) -> Result<(), OracleError> {
+        let Some(mut asset) = self.state.asset(&token).await.expect("Failed to get asset") else {
+            return Err(OracleError::UnknownToken(token));
+        };
+        config.validate()?;
+        asset.config = config;
+        self.state
+            .assets
+            .insert(&asset.symbol, asset.clone())
+            .expect("Failed to insert asset");
+        Ok(())
+    }
+
+    /// Store the reputation policy; it applies from the next scored round
+    fn handle_configure_reputation(&mut self, policy: ReputationPolicy) -> Result<(), OracleError> {
+        policy.validate()?;
+        self.state.reputation_policy.set(policy);
+        Ok(())
+    }
+
+    /// Store a registered asset, keeping its pair resolvable to it
+    async fn handle_register_asset(&mut self, asset: Asset) -> Result<(), OracleError> {
+        for (field, value) in [("symbol", &asset.symbol), ("base", &asset.base), ("quote", &asset.quote)] {
+            if value.is_empty() || value.contains('/') {
+                return Err(OracleError::InvalidAsset(format!(
+                    "{} must be non-empty and without '/'",
+                    field
+                )));
+            }
+        }
+        asset.config.validate()?;
+
+        let pair = asset.pair();
+        let paired = self
+            .state
+            .asset_pairs
+            .get(&pair)
+            .await
+            .expect("Failed to get asset pair");
+        if let Some(symbol) = paired.filter(|symbol| *symbol != asset.symbol) {
+            return Err(OracleError::InvalidAsset(format!(
+                "pair {} is already registered as {}",
+                pair, symbol
+            )));
+        }
+        let previous = self
+            .state
+            .assets
+            .get(&asset.symbol)
+            .await
+            .expect("Failed to get asset");
+        if let Some(previous) = previous {
+            self.state
+                .asset_pairs
+                .remove(&previous.pair())
+                .expect("Failed to remove asset pair");
+        }
+
+        self.state
+            .asset_pairs
+            .insert(&pair, asset.symbol.clone())
+            .expect("Failed to insert asset pair");
+        self.state
+            .assets
+            .insert(&asset.symbol.clone(), asset)
+            .expect("Failed to insert asset");
+        Ok(())
+    }
+
+    /// Append a published price to the token's series, pruning observations
+    /// older than the longest supported TWAP window. The series must stay
+    /// sorted, so a price older than the last observation is skipped.
+    async fn record_price_point(
+        &mut self,
+        token: &str,
+        price: Price,
+        vwap: Price,
+        volume: f64,
         timestamp: u64,
     ) {
-        let price_data = PriceData {
-            token: token.clone(),
-            price: aggregated_price,
-            timestamp,
-            source: format!("{} oracles", oracle_inputs.len()),
-            network: "Multi-Oracle".to_string(),
-            median,
-            twap,
+        let series = self
+            .state
+            .price_series
+            .load_entry_mut(token)
+            .await
+            .expect("Failed to load price series");
+        let last = series.back().await.expect("Failed to read price series");
+        if let Some(last) = last.filter(|last| timestamp < last.timestamp) {
+            log::warn!(
+                "Skipped {} price point at {}, older than the last one at {}",
+                token,
+                timestamp,
+                last.timestamp
+            );
+            return;
+        }
+        series.push_back(PricePoint {
+            price,
             vwap,
-        };
+            volume,
+            timestamp,
+        });
 
+        // Keep the last observation before the cutoff: it is the price in effect there
+        let cutoff = timestamp.saturating_sub(MAX_TWAP_WINDOW_SECS * MICROS_PER_SECOND);
+        loop {
+            let front = series.read_front(2).await.expect("Failed to read price series");
+            match front.get(1) {
+                Some(next) if next.timestamp <= cutoff => series.delete_front(),
+                _ => break,
+            }
+        }
+    }
+
+    /// Move a token that just published to the front of the recently updated feed
+    async fn mark_updated(&mut self, token: &str) {
+        let token = token.to_string();
+        if let Some(previous) = self
+            .state
+            .recent_update_keys
+            .get(&token)
+            .await
+            .expect("Failed to get update key")
+        {
+            self.state
+                .recent_updates
+                .remove(&previous)
+                .expect("Failed to remove update key");
+        }
+
+        let publication = *self.state.publication_count.get();
+        let key = u128::from(u64::MAX - publication);
+        self.state
+            .recent_updates
+            .insert(&key, token.clone())
+            .expect("Failed to insert update key");
         self.state
+            .recent_update_keys
+            .insert(&token, key)
+            .expect("Failed to insert update key");
+        self.state.publication_count.set(publication + 1);
+    }
+
+    /// Emit `event` on the oracle stream and keep it in the chain's event log,
+    /// so clients woken by a new block can read what happened since their cursor
+    fn emit_event(&mut self, event: OracleEvent) {
+        self.runtime.emit(ORACLE_STREAM_NAME.into(), &event);
+
+        let sequence = *self.state.event_count.get();
+        self.state.events.push_back(OracleEventRecord {
+            sequence,
+            timestamp: self.runtime.system_time().micros(),
+            event,
+        });
+        self.state.event_count.set(sequence + 1);
+        while self.state.events.count() > MAX_EVENT_LOG_LEN {
+            self.state.events.delete_front();
+        }
+    }
+
+    /// Append a published price to the token's history, dropping entries
+    /// beyond the token's retention
+    async fn record_history(&mut self, price_data: &PriceData) {
+        let config = self
+            .state
+            .token_config(&price_data.token)
+            .await
+            .expect("Failed to get token config");
+        let history = self
+            .state
+            .price_history
+            .load_entry_mut(&price_data.token)
+            .await
+            .expect("Failed to load price history");
+        history.push_back(price_data.clone());
+
+        let cutoff = price_data
+            .timestamp
+            .saturating_sub(config.history_retention_secs.saturating_mul(MICROS_PER_SECOND));
+        while history.count() > config.max_history_len as usize {
+            history.delete_front();
+        }
+        while let Some(oldest) = history.front().await.expect("Failed to read price history") {
+            if oldest.timestamp >= cutoff {
+                break;
+            }
+            history.delete_front();
+        }
+    }
+
+    /// Price observations of a token covering the longest of `windows_secs`
+    async fn price_points_for_windows(
+        &self,
+        token: &str,
+        now: u64,
+        windows_secs: &[u64],
+    ) -> Vec<PricePoint> {
+        let longest = windows_secs.iter().copied().max().unwrap_or(0);
+        self.state
+            .price_points_since(token, now.saturating_sub(longest * MICROS_PER_SECOND))
+            .await
+            .expect("Failed to read price series")
+    }
+
+    /// Newest stored candle of a token, once its legacy candles are migrated
+    async fn latest_candle(&self, token: &str, interval: CandleInterval) -> Option<Candle> {
+        let candles = self
+            .state
+            .candles
+            .try_load_entry(&(token.to_string(), interval))
+            .await
+            .expect("Failed to load candles")?;
+        let mut latest = None;
+        candles
+            .for_each_index_value_while(|_key, candle| {
+                latest = Some(candle.into_owned());
+                Ok(false)
+            })
+            .await
+            .expect("Failed to read candles");
+        latest
+    }
+
+    /// Fold a published price into the current candle of every interval,
+    /// closing the previous candle when a new interval has started
+    async fn update_candles(&mut self, token: &str, price: Price, volume: f64, timestamp: u64) {
+        for interval in CandleInterval::ALL {
+            self.migrate_legacy_candles(token, interval).await;
+
+            let bucket_start = interval.bucket_start(timestamp);
+            let latest = self.latest_candle(token, interval).await;
+            let candle = match latest {
+                Some(mut candle) if candle.timestamp == bucket_start => {
+                    candle.high = candle.high.max(price);
+                    candle.low = candle.low.min(price);
+                    candle.close = price;
+                    candle.volume += volume;
+                    candle
+                }
+                latest => {
+                    if let Some(closed) = latest.filter(|candle| candle.timestamp < bucket_start) {
+                        self.close_candle(token, interval, closed).await;
+                    }
+                    Candle {
+                        open: price,
+                        high: price,
+                        low: price,
+                        close: price,
+                        volume,
+                        timestamp: bucket_start,
+                    }
+                }
+            };
+            self.insert_candle(token, interval, candle).await;
+        }
+    }
+
+    /// Announce a candle as closed, unless it already was, returning whether
+    /// it was announced now
+    async fn close_candle(&mut self, token: &str, interval: CandleInterval, candle: Candle) -> bool {
+        let entry = (token.to_string(), interval);
+        let closed = self
+            .state
+            .closed_candles
+            .get(&entry)
+            .await
+            .expect("Failed to get closed candle");
+        if closed.is_some_and(|start| start >= candle.timestamp) {
+            return false;
+        }
+        self.state
+            .closed_candles
+            .insert(&entry, candle.timestamp)
+            .expect("Failed to insert closed candle");
+        self.emit_event(OracleEvent::CandleClosed {
+            token: token.to_string(),
+            interval,
+            candle,
+        });
+        true
+    }
+
+    /// Close the latest candles whose interval ended by `now`, so tokens that
+    /// stopped publishing still see theirs closed. Returns how many were
+    /// announced.
+    async fn close_ended_candles(&mut self, now: u64) -> usize {
+        let tokens = self
+            .state
             .prices
-            .insert(&token, price_data.clone())
-            .expect("Failed to insert price");
+            .indices()
+            .await
+            .expect("Failed to get prices");
+        let mut closed = 0;
+        for token in tokens {
+            for interval in CandleInterval::ALL {
+                let Some(candle) = self.latest_candle(&token, interval).await else {
+                    continue;
+                };
+                if candle.timestamp.saturating_add(interval.duration_micros()) <= now
+                    && self.close_candle(&token, interval, candle).await
+                {
+                    closed += 1;
+                }
+            }
+        }
+        closed
+    }
 
-        self.state.latest_price.set(Some(price_data));
-
-        // Emit event
-        self.runtime.emit(
-            ORACLE_STREAM_NAME.into(),
-            &OracleEvent::PriceUpdate {
-                token: token.clone(),
-                price: aggregated_price,
-                aggregated_price,
-                median,
-                twap,
-                vwap,
-                oracle_breakdown: oracle_inputs,
-                timestamp,
-            },
-        );
+    /// Store a candle in its interval bucket, replacing the candle already there.
+    /// Returns whether the candle was stored.
+    ///
+    /// An interval keeps the candles of its latest `max_candles` buckets: a
+    /// newer candle evicts the stored candles that fall out of that span, and
+    /// a candle older than the span isn't stored.
+    async fn insert_candle(&mut self, token: &str, interval: CandleInterval, mut candle: Candle) -> bool {
+        let bucket_start = interval.bucket_start(candle.timestamp);
+        candle.timestamp = bucket_start;
 
-        self.check_alerts(&token, aggregated_price).await;
+        let duration = interval.duration_micros();
+        let max_candles = interval.max_candles() as u64;
+        let span = (max_candles - 1) * duration;
+        let latest = self.latest_candle(token, interval).await;
+        let candles = self
+            .state
+            .candles
+            .load_entry_mut(&(token.to_string(), interval))
+            .await
+            .expect("Failed to load candles");
+        match latest.map(|latest| latest.timestamp) {
+            Some(latest) if bucket_start < latest.saturating_sub(span) => return false,
+            Some(latest) if bucket_start > latest => {
+                // Keys run newest to oldest, so the candles that fell out of
+                // the span are the ones past the oldest kept key
+                let oldest_kept = candle_key(bucket_start.saturating_sub(span));
+                let mut evicted = Vec::new();
+                candles
+                    .for_each_index(|key| {
+                        if key > oldest_kept {
+                            evicted.push(key);
+                        }
+                        Ok(())
+                    })
+                    .await
+                    .expect("Failed to read candles");
+                for key in evicted {
+                    candles.remove(&key).expect("Failed to remove candle");
+                }
+            }
+            _ => {}
+        }
+        candles
+            .insert(&candle_key(bucket_start), candle)
+            .expect("Failed to insert candle");
+        true
     }
 
-    /// Request aggregation from providers
-    async fn handle_request_aggregation(&mut self, token: String) {
-        let requester = self.runtime.chain_id();
-        let params = self.runtime.application_parameters();
-        
-        self.send_message(
-            params.aggregator_chain,
-            OracleMessage::RequestPrice { token, requester },
-        );
+    /// Move a token's candles out of the legacy per-token `Vec` layout
+    async fn migrate_legacy_candles(&mut self, token: &str, interval: CandleInterval) {
+        let Some(candles) = self
+            .state
+            .legacy_candles(token, interval)
+            .await
+            .expect("Failed to get candles")
+        else {
+            return;
+        };
+        let legacy = match interval {
+            CandleInterval::OneSecond => &mut self.state.candles_1s,
+            CandleInterval::OneMinute => &mut self.state.candles_1m,
+            CandleInterval::OneHour => &mut self.state.candles_1h,
+            CandleInterval::TwentyFourHour => &mut self.state.candles_24h,
+            _ => return,
+        };
+        legacy.remove(token).expect("Failed to remove legacy candles");
+
+        log::info!("Migrating {} {:?} candles for {}", candles.len(), interval, token);
+        for candle in candles {
+            self.insert_candle(token, interval, candle).await;
+        }
     }
 
     /// Update provider reputation
@@ -374,15 +1752,7 @@ impl OracleContract {
             .get(source)
             .await
             .expect("Failed to get reputation")
-            .unwrap_or(OracleReputation {
-                source: source.to_string(),
-                accuracy: 1.0,
-                latency_average: 0,
-                uptime: 1.0,
-                variance: 0.0,
-                total_updates: 0,
-                last_update: 0,
-            });
+            .unwrap_or_else(|| OracleReputation::new(source.to_string()));
 
         reputation.total_updates += 1;
         reputation.last_update = timestamp;
@@ -393,6 +1763,26 @@ impl OracleContract {
             .expect("Failed to update reputation");
     }
 
+    /// Count a submission excluded from aggregation against its source
+    async fn record_rejection(&mut self, source: &str) {
+        let Some(mut reputation) = self
+            .state
+            .oracle_stats
+            .get(source)
+            .await
+            .expect("Failed to get reputation")
+        else {
+            return;
+        };
+
+        reputation.rejected_updates += 1;
+
+        self.state
+            .oracle_stats
+            .insert(source, reputation)
+            .expect("Failed to update reputation");
+    }
+
     /// Send message to another chain
     fn send_message(&mut self, destination: ChainId, message: OracleMessage) {
         self.runtime
@@ -401,43 +1791,99 @@ impl OracleContract {
             .send_to(destination);
     }
 
-    /// Handle OHLC candle update
+    /// Backfill a past candle, replacing any candle in its bucket
     async fn handle_candle_update(
         &mut self,
         token: String,
         interval: CandleInterval,
-        candle: Candle,
-    ) {
-        let map_view = match interval {
-            CandleInterval::OneSecond => &mut self.state.candles_1s,
-            CandleInterval::OneMinute => &mut self.state.candles_1m,
-            CandleInterval::OneHour => &mut self.state.candles_1h,
-            CandleInterval::TwentyFourHour => &mut self.state.candles_24h,
+        mut candle: Candle,
+    ) -> Result<(), OracleError> {
+        let Some(Asset { symbol: token, config, .. }) = self.state.asset(&token).await.expect("Failed to get asset") else {
+            return Err(OracleError::UnknownToken(token));
         };
+        // Store the prices at the token's decimals, like the live candles
+        for price in [&mut candle.open, &mut candle.high, &mut candle.low, &mut candle.close] {
+            *price = price
+                .rescale(config.decimals)
+                .filter(Price::is_valid)
+                .ok_or(OracleError::InvalidPrice(*price))?;
+        }
+        if candle.low > candle.open.min(candle.close) || candle.high < candle.open.max(candle.close) {
+            return Err(OracleError::InvalidCandle(
+                "open and close must lie between low and high".to_string(),
+            ));
+        }
+        if !(candle.volume.is_finite() && candle.volume >= 0.0) {
+            return Err(OracleError::InvalidCandle(
+                "volume must not be negative".to_string(),
+            ));
+        }
+        // The current interval is built from the published prices
+        let now = self.runtime.system_time().micros();
+        if interval.bucket_start(candle.timestamp) >= interval.bucket_start(now) {
+            return Err(OracleError::InvalidCandle(
+                "only closed intervals can be backfilled".to_string(),
+            ));
+        }
 
-        // Get existing candles or create new vec
-        let mut candles = map_view
-            .get(&token)
-            .await
-            .expect("Failed to get candles")
-            .unwrap_or_default();
-
-        // Add new candle
-        candles.push(candle);
-
-        // Keep only last 1000 candles to prevent unbounded growth
-        if candles.len() > 1000 {
-            candles.drain(0..candles.len() - 1000);
+        let timestamp = candle.timestamp;
+        self.migrate_legacy_candles(&token, interval).await;
+        if !self.insert_candle(&token, interval, candle).await {
+            return Err(OracleError::InvalidCandle(
+                "candle is older than the kept candles".to_string(),
+            ));
         }
+        self.roll_up_candles(&token, interval, timestamp).await;
+        Ok(())
+    }
 
-        // Store updated candles
-        map_view
-            .insert(&token, candles)
-            .expect("Failed to insert candles");
+    /// Rebuild the longer candles containing `timestamp` from the shorter ones,
+    /// starting with those rolled up from `interval`
+    async fn roll_up_candles(&mut self, token: &str, interval: CandleInterval, timestamp: u64) {
+        let mut source = interval;
+        while let Some(target) = CandleInterval::ALL
+            .into_iter()
+            .find(|target| target.rollup_source() == Some(source))
+        {
+            let bucket_start = target.bucket_start(timestamp);
+            let bucket_end = bucket_start + target.duration_micros() - 1;
+            let candles = self
+                .state
+                .candle_range(token, source, bucket_start, bucket_end)
+                .await
+                .expect("Failed to read candles");
+            if let Some(candle) = roll_up(&candles, bucket_start) {
+                self.migrate_legacy_candles(token, target).await;
+                self.insert_candle(token, target, candle).await;
+            }
+            source = target;
+        }
     }
 
     /// Set or update price alert
-    async fn handle_set_alert(&mut self, user_id: String, alert: AlertConfig) {
+    async fn handle_set_alert(&mut self, user_id: String, mut alert: AlertConfig) -> Result<(), OracleError> {
+        if !alert.threshold_value.is_valid() {
+            return Err(OracleError::InvalidPrice(alert.threshold_value));
+        }
+        alert.token = match self.resolve_asset(&alert.token).await {
+            Ok(asset) => asset.symbol,
+            Err(OracleError::UnknownToken(token)) => {
+                // Chains receiving aggregation results have prices but no registry
+                let token = normalize_symbol(&token);
+                let priced = self
+                    .state
+                    .prices
+                    .contains_key(&token)
+                    .await
+                    .expect("Failed to get price");
+                if !priced {
+                    return Err(OracleError::UnknownToken(token));
+                }
+                token
+            }
+            Err(error) => return Err(error),
+        };
+
         let mut user_alerts = self
             .state
             .alerts
@@ -457,63 +1903,205 @@ impl OracleContract {
             .alerts
             .insert(&user_id, user_alerts)
             .expect("Failed to insert alerts");
+        Ok(())
     }
 
     /// Remove price alert
-    async fn handle_remove_alert(&mut self, user_id: String, alert_id: String) {
-        if let Ok(Some(mut user_alerts)) = self.state.alerts.get(&user_id).await {
-            user_alerts.retain(|a| a.id != alert_id);
-            self.state
-                .alerts
-                .insert(&user_id, user_alerts)
-                .expect("Failed to insert alerts");
+    async fn handle_remove_alert(&mut self, user_id: String, alert_id: String) -> Result<(), OracleError> {
+        let mut user_alerts = self
+            .state
+            .alerts
+            .get(&user_id)
+            .await
+            .expect("Failed to get alerts")
+            .unwrap_or_default();
+        let count = user_alerts.len();
+        user_alerts.retain(|a| a.id != alert_id);
+        if user_alerts.len() == count {
+            return Err(OracleError::AlertNotFound(alert_id));
         }
+        self.state
+            .alerts
+            .insert(&user_id, user_alerts)
+            .expect("Failed to insert alerts");
+        Ok(())
     }
 
-    /// Update oracle reputation metrics
+    /// Fold a source's showing in one round into its reputation: its
+    /// submission's relative `deviation` from the answer, if there was one,
+    /// its `latency` since the round started, if it submitted, and whether it
+    /// took part at all. Returns the updated reputation.
     async fn handle_reputation_update(
         &mut self,
         source: String,
-        accuracy: f64,
-        latency: u64,
-        uptime: f64,
-    ) {
-        let timestamp = self.runtime.system_time().micros();
-
+        deviation: Option<f64>,
+        latency: Option<u64>,
+        participated: bool,
+    ) -> OracleReputation {
         let mut reputation = self
             .state
             .oracle_stats
             .get(&source)
             .await
             .expect("Failed to get reputation")
-            .unwrap_or(OracleReputation {
-                source: source.clone(),
-                accuracy: 0.0,
-                latency_average: 0,
-                uptime: 0.0,
-                variance: 0.0,
-                total_updates: 0,
-                last_update: 0,
-            });
+            .unwrap_or_else(|| OracleReputation::new(source.clone()));
+        let policy = self.state.reputation_policy.get().clone();
+        let now = self.runtime.system_time().micros();
+        reputation.decay(now, policy.recovery_half_life_secs);
+        reputation.scored_at = now;
 
         // Update metrics with exponential moving average
-        let alpha = 0.1; // Smoothing factor
-        reputation.accuracy = reputation.accuracy * (1.0 - alpha) + accuracy * alpha;
-        reputation.latency_average =
-            ((reputation.latency_average as f64) * (1.0 - alpha) + (latency as f64) * alpha)
-                as u64;
+        let alpha = REPUTATION_SMOOTHING;
+        if let Some(deviation) = deviation {
+            let accuracy = accuracy_score(deviation);
+            reputation.accuracy = reputation.accuracy * (1.0 - alpha) + accuracy * alpha;
+            reputation.variance = reputation.variance * (1.0 - alpha) + deviation.powi(2) * alpha;
+        }
+        if let Some(latency) = latency {
+            reputation.latency_average =
+                ((reputation.latency_average as f64) * (1.0 - alpha) + (latency as f64) * alpha)
+                    as u64;
+        }
+        let uptime = if participated { 1.0 } else { 0.0 };
         reputation.uptime = reputation.uptime * (1.0 - alpha) + uptime * alpha;
-        reputation.total_updates += 1;
-        reputation.last_update = timestamp;
+        reputation.rounds += 1;
+        if participated {
+            reputation.participation += 1;
+        }
+
+        let quarantined = policy.quarantines(&reputation);
+        if quarantined != reputation.quarantined {
+            reputation.quarantined = quarantined;
+            let (accuracy, uptime) = (reputation.accuracy, reputation.uptime);
+            if quarantined {
+                log::warn!("Quarantined provider {}: accuracy {:.3}, uptime {:.3}", source, accuracy, uptime);
+                self.emit_event(OracleEvent::ProviderQuarantined {
+                    source: source.clone(),
+                    accuracy,
+                    uptime,
+                });
+            } else {
+                log::info!("Released provider {}: accuracy {:.3}, uptime {:.3}", source, accuracy, uptime);
+                self.emit_event(OracleEvent::ProviderReleased {
+                    source: source.clone(),
+                    accuracy,
+                    uptime,
+                });
+            }
+        }
 
         self.state
             .oracle_stats
-            .insert(&source, reputation)
+            .insert(&source, reputation.clone())
             .expect("Failed to insert reputation");
+        reputation
+    }
+
+    /// Append a scored round to a provider's reputation history, dropping the
+    /// oldest beyond `MAX_REPUTATION_HISTORY_LEN`
+    async fn record_reputation_sample(&mut self, source: &str, sample: ReputationSample) {
+        let history = self
+            .state
+            .reputation_history
+            .load_entry_mut(source)
+            .await
+            .expect("Failed to load reputation history");
+        history.push_back(sample);
+        while history.count() > MAX_REPUTATION_HISTORY_LEN {
+            history.delete_front();
+        }
+    }
+
+    /// Whether a source's submissions are left out of aggregation
+    async fn is_quarantined(&self, source: &str) -> bool {
+        self.state
+            .oracle_stats
+            .get(source)
+            .await
+            .expect("Failed to get reputation")
+            .is_some_and(|reputation| reputation.quarantined)
+    }
+
+    /// How many of `sources` are not quarantined
+    async fn count_eligible(&self, sources: impl IntoIterator<Item = &String>) -> usize {
+        let mut eligible = 0;
+        for source in sources {
+            if !self.is_quarantined(source).await {
+                eligible += 1;
+            }
+        }
+        eligible
+    }
+
+    /// Number of the token's providers that aren't quarantined
+    async fn count_eligible_providers(&self, token: &str) -> u64 {
+        let providers = self
+            .state
+            .token_providers(token)
+            .await
+            .expect("Failed to get token providers");
+        self.count_eligible(providers.iter().map(|(source, _chain_id)| source))
+            .await as u64
+    }
+
+    /// Score every source expected in a closed round against its answer.
+    /// Sources that should have submitted but didn't lose uptime, once the
+    /// round `timed_out`: a quorum closing it early doesn't count against
+    /// providers whose submissions were still on their way.
+    async fn score_round(&mut self, round: &OracleRound, timed_out: bool) {
+        let mut expected: Vec<String> = if timed_out {
+            self.state
+                .token_providers(&round.token)
+                .await
+                .expect("Failed to get token providers")
+                .into_iter()
+                .map(|(source, _chain_id)| source)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        for source in &round.sources {
+            if !expected.contains(source) {
+                expected.push(source.clone());
+            }
+        }
+
+        for source in expected {
+            let submission = round.submissions.iter().rev().find(|input| input.source == source);
+            let deviation = submission.zip(round.answer).and_then(|(input, answer)| {
+                let answer = answer.to_f64();
+                (answer > 0.0).then(|| (input.price.to_f64() - answer).abs() / answer)
+            });
+            let latency = submission.map(|input| {
+                input
+                    .timestamp
+                    .saturating_add(input.latency)
+                    .saturating_sub(round.started_at)
+            });
+            let reputation = self
+                .handle_reputation_update(source.clone(), deviation, latency, submission.is_some())
+                .await;
+            let sample = ReputationSample {
+                token: round.token.clone(),
+                round_id: round.round_id,
+                timestamp: reputation.scored_at,
+                participated: submission.is_some(),
+                price: submission.map(|input| input.price),
+                answer: round.answer,
+                deviation,
+                latency,
+                accuracy: reputation.accuracy,
+                uptime: reputation.uptime,
+                variance: reputation.variance,
+                latency_average: reputation.latency_average,
+                quarantined: reputation.quarantined,
+            };
+            self.record_reputation_sample(&source, sample).await;
+        }
     }
 
     /// Check if any alerts should be triggered
-    async fn check_alerts(&mut self, token: &str, price: f64) {
+    async fn check_alerts(&mut self, token: &str, price: Price) {
         let mut triggered_alerts = Vec::new();
         
         let _ = self
@@ -538,15 +2126,373 @@ impl OracleContract {
 
         // Emit events for triggered alerts
         for (user_id, alert) in triggered_alerts {
-            self.runtime.emit(
-                ORACLE_STREAM_NAME.into(),
-                &OracleEvent::AlertTriggered {
-                    user_id,
-                    alert_id: alert.id,
-                    token: alert.token,
-                    price,
-                },
-            );
+            self.emit_event(OracleEvent::AlertTriggered {
+                user_id,
+                alert_id: alert.id,
+                token: alert.token,
+                price,
+            });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use linera_sdk::{
+        linera_base_types::{ChainId, CryptoHash, Timestamp},
+        util::BlockingWait,
+        views::View,
+        Contract, ContractRuntime,
+    };
+    use oracle_microchain::{
+        DuplicatePolicy, OracleError, OracleMessage, OracleParameters, OracleResponse, OracleRound, Operation,
+        Price, TokenConfig, MICROS_PER_SECOND,
+    };
+
+    use super::{OracleContract, OracleState};
+
+    /// 2024-01-01 00:00 UTC
+    const NOW: u64 = 1_704_067_200 * MICROS_PER_SECOND;
+
+    fn chain(name: &str) -> ChainId {
+        ChainId(CryptoHash::test_hash(name))
+    }
+
+    /// Contract on a chain that is both the master and the aggregator chain,
+    /// with `BTC` registered and each of `sources` registered on its own chain
+    fn create_oracle(sources: &[&str]) -> OracleContract {
+        let master = chain("master");
+        let runtime = ContractRuntime::new()
+            .with_application_parameters(OracleParameters {
+                master_chain: master,
+                aggregator_chain: master,
+            })
+            .with_chain_id(master)
+            .with_system_time(Timestamp::from(NOW));
+        let mut contract = OracleContract {
+            state: OracleState::load(runtime.root_view_storage_context())
+                .blocking_wait()
+                .expect("Failed to read from mock key value store"),
+            runtime,
+        };
+        contract.instantiate(()).blocking_wait();
+
+        execute(&mut contract, Operation::RegisterAsset {
+            symbol: "BTC".to_string(),
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            display_name: "Bitcoin".to_string(),
+            config: None,
+        })
+        .expect("Failed to register asset");
+        for source in sources {
+            execute(&mut contract, Operation::RegisterProvider {
+                provider_chain: chain(source),
+                source_name: source.to_string(),
+            })
+            .expect("Failed to register provider");
+        }
+        contract
+    }
+
+    fn execute(contract: &mut OracleContract, operation: Operation) -> Result<OracleResponse, OracleError> {
+        contract.execute_operation(operation).blocking_wait()
+    }
+
+    /// Deliver a `BTC` price submission of `source`, dated now, sent from `origin`
+    fn submit_from(
+        contract: &mut OracleContract,
+        origin: ChainId,
+        source: &str,
+        mantissa: u64,
+    ) -> Result<(), OracleError> {
+        let timestamp = contract.runtime.system_time().micros();
+        let message = OracleMessage::SubmitPrice {
+            token: "BTC".to_string(),
+            price: Price::new(mantissa, 8),
+            source: source.to_string(),
+            timestamp,
+            volume: None,
+        };
+        contract.handle_message(origin, message).blocking_wait()
+    }
+
+    /// Deliver a `BTC` price submission of `source` from its own chain
+    fn submit(contract: &mut OracleContract, source: &str, mantissa: u64) -> Result<(), OracleError> {
+        submit_from(contract, chain(source), source, mantissa)
+    }
+
+    fn open_round(contract: &OracleContract) -> Option<OracleRound> {
+        contract
+            .state
+            .open_rounds
+            .get("BTC")
+            .blocking_wait()
+            .expect("Failed to get open round")
+    }
+
+    fn published_price(contract: &OracleContract) -> Option<Price> {
+        let price_data = contract
+            .state
+            .prices
+            .get("BTC")
+            .blocking_wait()
+            .expect("Failed to get price");
+        price_data.map(|price_data| price_data.price)
+    }
+
+    fn configure(contract: &mut OracleContract, config: TokenConfig) {
+        execute(contract, Operation::ConfigureToken {
+            token: "BTC".to_string(),
+            config,
+        })
+        .expect("Failed to configure token");
+    }
+
+    fn advance_secs(contract: &mut OracleContract, secs: u64) {
+        let now = contract.runtime.system_time().micros() + secs * MICROS_PER_SECOND;
+        contract.runtime.set_system_time(Timestamp::from(now));
+    }
+
+    fn sent_messages(contract: &OracleContract) -> usize {
+        contract.runtime.created_send_message_requests().len()
+    }
+
+    #[test]
+    fn submissions_of_unknown_sources_are_refused() {
+        let mut contract = create_oracle(&[]);
+        assert_eq!(
+            submit(&mut contract, "a", 100),
+            Err(OracleError::UnknownProvider("a".to_string()))
+        );
+        assert!(open_round(&contract).is_none());
+    }
+
+    #[test]
+    fn submissions_from_another_providers_chain_are_refused() {
+        let mut contract = create_oracle(&["a", "b"]);
+        assert_eq!(
+            submit_from(&mut contract, chain("b"), "a", 100),
+            Err(OracleError::UnauthorizedSource {
+                source_name: "a".to_string(),
+                expected: chain("a"),
+                actual: chain("b"),
+            })
+        );
+        assert_eq!(submit(&mut contract, "a", 100), Ok(()));
+    }
+
+    #[test]
+    fn only_the_master_chain_registers_providers() {
+        let mut contract = create_oracle(&["a"]);
+        let message = OracleMessage::RegisterProvider {
+            provider_chain: chain("b"),
+            source_name: "b".to_string(),
+        };
+        assert_eq!(
+            contract.handle_message(chain("a"), message).blocking_wait(),
+            Err(OracleError::Unauthorized(chain("a")))
+        );
+        assert_eq!(
+            submit(&mut contract, "b", 100),
+            Err(OracleError::UnknownProvider("b".to_string()))
+        );
+    }
+
+    #[test]
+    fn price_requests_fan_out_only_from_the_aggregator_chain() {
+        let mut contract = create_oracle(&["a", "b"]);
+        let request = || OracleMessage::RequestPrice {
+            token: "BTC".to_string(),
+            requester: chain("consumer"),
+        };
+
+        let sent = sent_messages(&contract);
+        contract.handle_message(chain("consumer"), request()).blocking_wait().unwrap();
+        assert_eq!(sent_messages(&contract), sent + 2);
+
+        // A provider chain receiving the request doesn't pass it on
+        contract.runtime.set_chain_id(chain("a"));
+        contract.handle_message(chain("master"), request()).blocking_wait().unwrap();
+        assert_eq!(sent_messages(&contract), sent + 2);
+    }
+
+    #[test]
+    fn rounds_close_once_three_providers_submitted() {
+        let mut contract = create_oracle(&["a", "b", "c", "d"]);
+        submit(&mut contract, "a", 100).unwrap();
+        submit(&mut contract, "b", 101).unwrap();
+        assert_eq!(open_round(&contract).map(|round| round.sources.len()), Some(2));
+        assert!(published_price(&contract).is_none());
+
+        submit(&mut contract, "c", 102).unwrap();
+        assert!(open_round(&contract).is_none());
+        assert_eq!(published_price(&contract).map(|price| price.mantissa), Some(101));
+        // Everything the round wrote can be encoded
+        contract.store().blocking_wait();
+    }
+
+    #[test]
+    fn rounds_close_once_every_provider_submitted_when_fewer_than_three() {
+        let mut contract = create_oracle(&["a", "b"]);
+        submit(&mut contract, "a", 100).unwrap();
+        assert!(published_price(&contract).is_none());
+        submit(&mut contract, "b", 100).unwrap();
+        assert_eq!(published_price(&contract).map(|price| price.mantissa), Some(100));
+    }
+
+    #[test]
+    fn timed_out_rounds_without_enough_sources_are_abandoned() {
+        let mut contract = create_oracle(&["a", "b", "c"]);
+        submit(&mut contract, "a", 100).unwrap();
+        let close = || Operation::CloseRound {
+            token: "BTC".to_string(),
+        };
+        assert!(matches!(
+            execute(&mut contract, close()),
+            Err(OracleError::RoundNotExpired { round_id: 1, .. })
+        ));
+
+        advance_secs(&mut contract, TokenConfig::default().max_round_age_secs);
+        assert_eq!(
+            execute(&mut contract, close()),
+            Ok(OracleResponse::Abandoned { round_id: 1 })
+        );
+        assert!(published_price(&contract).is_none());
+        assert_eq!(
+            execute(&mut contract, close()),
+            Err(OracleError::NoOpenRound("BTC".to_string()))
+        );
+    }
+
+    #[test]
+    fn timed_out_rounds_with_enough_sources_are_finalized() {
+        let mut contract = create_oracle(&["a", "b", "c"]);
+        configure(&mut contract, TokenConfig {
+            min_sources: 1,
+            min_provider_fraction: 1.0,
+            ..TokenConfig::default()
+        });
+        submit(&mut contract, "a", 100).unwrap();
+        assert!(published_price(&contract).is_none());
+
+        advance_secs(&mut contract, TokenConfig::default().max_round_age_secs);
+        let response = execute(&mut contract, Operation::CloseRound {
+            token: "BTC".to_string(),
+        });
+        assert!(matches!(response, Ok(OracleResponse::Published { .. })));
+        assert_eq!(published_price(&contract).map(|price| price.mantissa), Some(100));
+    }
+
+    #[test]
+    fn resubmissions_replace_the_earlier_price_by_default() {
+        let mut contract = create_oracle(&["a", "b", "c"]);
+        submit(&mut contract, "a", 100).unwrap();
+        submit(&mut contract, "a", 105).unwrap();
+
+        let round = open_round(&contract).unwrap();
+        assert_eq!(round.sources, ["a"]);
+        assert_eq!(round.submissions.len(), 1);
+        assert_eq!(round.submissions[0].price.mantissa, 105);
+    }
+
+    #[test]
+    fn resubmissions_are_refused_when_the_first_wins() {
+        let mut contract = create_oracle(&["a", "b", "c"]);
+        configure(&mut contract, TokenConfig {
+            duplicate_policy: DuplicatePolicy::FirstWins,
+            ..TokenConfig::default()
+        });
+        submit(&mut contract, "a", 100).unwrap();
+        assert_eq!(
+            submit(&mut contract, "a", 105),
+            Err(OracleError::DuplicateSubmission {
+                source_name: "a".to_string(),
+                round_id: 1,
+            })
+        );
+        assert_eq!(open_round(&contract).unwrap().submissions[0].price.mantissa, 100);
+    }
+
+    #[test]
+    fn suspended_providers_are_refused_until_resumed() {
+        let mut contract = create_oracle(&["a", "b"]);
+        execute(&mut contract, Operation::SuspendProvider {
+            source_name: "a".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            submit(&mut contract, "a", 100),
+            Err(OracleError::ProviderSuspended("a".to_string()))
+        );
+
+        execute(&mut contract, Operation::ResumeProvider {
+            source_name: "a".to_string(),
+        })
+        .unwrap();
+        assert_eq!(submit(&mut contract, "a", 100), Ok(()));
+    }
+
+    #[test]
+    fn deregistered_providers_are_refused() {
+        let mut contract = create_oracle(&["a", "b"]);
+        submit(&mut contract, "a", 100).unwrap();
+        execute(&mut contract, Operation::DeregisterProvider {
+            source_name: "a".to_string(),
+        })
+        .unwrap();
+
+        // Its pending submission is dropped with it
+        assert!(open_round(&contract).is_none_or(|round| round.submissions.is_empty()));
+        assert_eq!(
+            submit(&mut contract, "a", 100),
+            Err(OracleError::UnknownProvider("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn rotated_providers_keep_their_previous_chain_for_the_grace_period() {
+        let mut contract = create_oracle(&["a", "b", "c"]);
+        execute(&mut contract, Operation::RotateProvider {
+            source_name: "a".to_string(),
+            new_chain: chain("a2"),
+            grace_period_secs: Some(30),
+        })
+        .unwrap();
+        assert_eq!(submit_from(&mut contract, chain("a"), "a", 100), Ok(()));
+        assert_eq!(submit_from(&mut contract, chain("a2"), "a", 100), Ok(()));
+
+        advance_secs(&mut contract, 31);
+        assert_eq!(submit_from(&mut contract, chain("a2"), "a", 100), Ok(()));
+        assert!(matches!(
+            submit_from(&mut contract, chain("a"), "a", 100),
+            Err(OracleError::UnauthorizedSource { .. })
+        ));
+    }
+
+    #[test]
+    fn the_only_provider_of_a_restricted_token_cannot_be_deregistered() {
+        let mut contract = create_oracle(&["a", "b"]);
+        execute(&mut contract, Operation::SetTokenProviders {
+            token: "BTC".to_string(),
+            sources: Some(vec!["a".to_string()]),
+        })
+        .unwrap();
+        assert_eq!(
+            execute(&mut contract, Operation::DeregisterProvider {
+                source_name: "a".to_string(),
+            }),
+            Err(OracleError::LastTokenProvider {
+                source_name: "a".to_string(),
+                token: "BTC".to_string(),
+            })
+        );
+        assert_eq!(submit(&mut contract, "a", 100), Ok(()));
+        assert_eq!(
+            execute(&mut contract, Operation::DeregisterProvider {
+                source_name: "b".to_string(),
+            }),
+            Ok(OracleResponse::Ok)
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Serde adapter for `f64` fields, as `#[serde(with = "float_bits")]`.
+//!
+//! BCS, which encodes the state, messages, operations and events, has no
+//! floating-point type, so compact formats get the value's IEEE 754 bits.
+//! Human-readable formats such as JSON keep the plain number.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An `f64` encoded as its bits in compact formats
+#[derive(Clone, Copy)]
+struct Bits(f64);
+
+impl Serialize for Bits {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_f64(self.0)
+        } else {
+            serializer.serialize_u64(self.0.to_bits())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Bits {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            f64::deserialize(deserializer).map(Bits)
+        } else {
+            u64::deserialize(deserializer).map(|bits| Bits(f64::from_bits(bits)))
+        }
+    }
+}
+
+pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    Bits(*value).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Bits::deserialize(deserializer)?.0)
+}
+
+/// The adapter for `Option<f64>` fields, as
+/// `#[serde(default, with = "float_bits::option")]`
+pub mod option {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Bits;
+
+    pub fn serialize<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(Bits).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+        Ok(Option::<Bits>::deserialize(deserializer)?.map(|bits| bits.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use linera_sdk::{bcs, serde_json};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Sample {
+        #[serde(with = "super")]
+        value: f64,
+        #[serde(default, with = "super::option")]
+        optional: Option<f64>,
+    }
+
+    #[test]
+    fn bcs_round_trips_the_bits() {
+        let sample = Sample {
+            value: 0.1,
+            optional: Some(-2.5),
+        };
+        let bytes = bcs::to_bytes(&sample).unwrap();
+        assert_eq!(&bytes[..8], &0.1f64.to_bits().to_le_bytes());
+        assert_eq!(bcs::from_bytes::<Sample>(&bytes).unwrap(), sample);
+    }
+
+    #[test]
+    fn json_keeps_numbers() {
+        let sample = Sample {
+            value: 0.5,
+            optional: None,
+        };
+        let json = serde_json::to_string(&sample).unwrap();
+        assert_eq!(json, r#"{"value":0.5,"optional":null}"#);
+        assert_eq!(serde_json::from_str::<Sample>(r#"{"value":0.5}"#).unwrap(), sample);
+    }
+}
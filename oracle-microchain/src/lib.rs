@@ -5,12 +5,13 @@ use linera_sdk::{
     linera_base_types::ChainId,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 pub struct OracleAbi;
 
 impl ContractAbi for OracleAbi {
     type Operation = Operation;
-    type Response = ();
+    type Response = Result<OracleResponse, OracleError>;
 }
 
 impl ServiceAbi for OracleAbi {
@@ -18,16 +19,79 @@ impl ServiceAbi for OracleAbi {
     type QueryResponse = Response;
 }
 
+pub mod aggregation;
+pub mod float_bits;
+pub mod price;
+
+pub use price::{Price, DEFAULT_PRICE_DECIMALS, MAX_PRICE_DECIMALS};
+
 pub const ORACLE_STREAM_NAME: &str = "oracle_price_feed";
 
+/// Default window of the TWAP published with each aggregated price (30 minutes)
+pub const DEFAULT_TWAP_WINDOW_SECS: u64 = 30 * 60;
+
+/// Default window of the rolling VWAP published with each aggregated price (30 minutes)
+pub const DEFAULT_VWAP_WINDOW_SECS: u64 = 30 * 60;
+
+/// Longest supported TWAP/VWAP window; older price observations are pruned (24 hours)
+pub const MAX_TWAP_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Default longest time a round waits for its quorum (1 minute)
+pub const DEFAULT_MAX_ROUND_AGE_SECS: u64 = 60;
+
+/// Default time a rotated provider's previous chain stays accepted (10 minutes)
+pub const DEFAULT_PROVIDER_GRACE_SECS: u64 = 10 * 60;
+
+/// Default age beyond which a submission is refused (2 minutes)
+pub const DEFAULT_MAX_SUBMISSION_AGE_SECS: u64 = 2 * 60;
+
+/// Default tolerance for submissions dated ahead of the chain's clock
+pub const DEFAULT_MAX_CLOCK_DRIFT_SECS: u64 = 10;
+
+/// Default interval after which a price without updates is stale (1 hour)
+pub const DEFAULT_HEARTBEAT_SECS: u64 = 60 * 60;
+
+/// Default age after which published prices leave the history (7 days)
+pub const DEFAULT_HISTORY_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Default number of published prices kept in the history
+pub const DEFAULT_MAX_HISTORY_LEN: u32 = 10_000;
+
+/// Number of emitted events kept on chain for `eventsSince` readers
+pub const MAX_EVENT_LOG_LEN: usize = 10_000;
+
+/// Relative deviation from a round's answer at which a submission scores
+/// zero accuracy (5%)
+pub const MAX_ACCURACY_DEVIATION: f64 = 0.05;
+
+/// Default time over which half of a provider's reputation shortfall is
+/// forgiven (1 day)
+pub const DEFAULT_RECOVERY_HALF_LIFE_SECS: u64 = 24 * 60 * 60;
+
+/// Scored rounds kept in each provider's reputation history
+pub const MAX_REPUTATION_HISTORY_LEN: usize = 10_000;
+
+/// Closed rounds kept per token, with their submissions
+pub const MAX_ROUND_HISTORY_LEN: u64 = 10_000;
+
+/// Weight of the latest round in the reputation moving averages
+pub const REPUTATION_SMOOTHING: f64 = 0.1;
+
+/// Timestamps are in microseconds, matching `system_time().micros()`
+pub const MICROS_PER_SECOND: u64 = 1_000_000;
+
 #[derive(Debug, Deserialize, Serialize, GraphQLMutationRoot)]
 pub enum Operation {
-    /// Submit price from oracle provider (called by provider chains)
+    /// Submit price from oracle provider. Only valid on the chain registered
+    /// for `source`, which forwards it to the aggregator chain.
     SubmitPrice {
         token: String,
-        price: f64,
+        price: Price,
         source: String,
         timestamp: u64,
+        /// Traded volume reported by the source, if known
+        #[serde(default, with = "float_bits::option")]
+        volume: Option<f64>,
     },
     /// Subscribe to price feed events
     SubscribeTo {
@@ -42,7 +106,35 @@ pub enum Operation {
         provider_chain: ChainId,
         source_name: String,
     },
-    /// Update OHLC candle data
+    /// Remove a provider, dropping its pending submissions (Master chain only).
+    /// Refused while it is the only provider a token is restricted to.
+    DeregisterProvider {
+        source_name: String,
+    },
+    /// Refuse a provider's submissions until it is resumed, dropping its
+    /// pending ones (Master chain only)
+    SuspendProvider {
+        source_name: String,
+    },
+    /// Accept a suspended provider's submissions again (Master chain only)
+    ResumeProvider {
+        source_name: String,
+    },
+    /// Move a provider to a new chain (Master chain only). The previous chain
+    /// stays accepted for `grace_period_secs`, by default
+    /// `DEFAULT_PROVIDER_GRACE_SECS`, so submissions in flight still land.
+    RotateProvider {
+        source_name: String,
+        new_chain: ChainId,
+        grace_period_secs: Option<u64>,
+    },
+    /// Set when providers are quarantined for a poor reputation (Master chain only)
+    ConfigureReputation {
+        policy: ReputationPolicy,
+    },
+    /// Backfill a past OHLC candle (Master chain only), with its prices
+    /// rescaled to the token's decimals; live candles are built from the
+    /// published prices
     UpdateCandle {
         token: String,
         interval: CandleInterval,
@@ -62,46 +154,548 @@ pub enum Operation {
     RequestAggregation {
         token: String,
     },
+    /// Register or update a supported asset (Master chain only). New assets
+    /// start enabled; without a `config`, the asset keeps its current
+    /// aggregation settings.
+    RegisterAsset {
+        symbol: String,
+        base: String,
+        quote: String,
+        display_name: String,
+        config: Option<TokenConfig>,
+    },
+    /// Restrict which providers may submit prices for a token, or allow all
+    /// of them again without `sources` (Master chain only). Quorums count only
+    /// these providers, so the list can't be empty.
+    SetTokenProviders {
+        token: String,
+        sources: Option<Vec<String>>,
+    },
+    /// Enable or disable a registered asset (Master chain only)
+    SetAssetEnabled {
+        symbol: String,
+        enabled: bool,
+    },
+    /// Configure a registered asset's aggregation settings (Master chain only)
+    ConfigureToken {
+        token: String,
+        config: TokenConfig,
+    },
+    /// Finalize or abandon a token's open round once it has timed out. Also
+    /// closes the candles of every token whose interval has ended, and
+    /// succeeds when it closed some even if the round can't be closed yet.
+    CloseRound {
+        token: String,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
 pub struct PriceData {
     pub token: String,
-    pub price: f64,
+    pub price: Price,
     pub timestamp: u64,
     pub source: String,
     pub network: String,
-    pub median: f64,
-    pub twap: f64,
-    pub vwap: f64,
+    pub median: Price,
+    pub twap: Price,
+    pub twap_window_secs: u64,
+    /// Rolling VWAP over `vwap_window_secs`, or the round VWAP without volume history
+    pub vwap: Price,
+    pub vwap_window_secs: u64,
+    /// VWAP of this round's submissions (plain mean when no volume was reported)
+    pub round_vwap: Price,
+    /// Total volume reported by this round's submissions
+    #[serde(with = "float_bits")]
+    pub volume: f64,
+    /// Unweighted mean of this round's submissions
+    pub mean: Price,
+    /// Weighting used to derive `price` from the submissions
+    pub weighting: WeightingStrategy,
+    /// How much the accepted submissions disagreed
+    pub confidence: PriceConfidence,
+    /// Aggregation round that produced this price
+    pub round_id: u64,
+}
+
+/// Age of a token's latest price compared to its heartbeat
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct PriceFreshness {
+    pub token: String,
+    /// When the latest price was published
+    pub updated_at: u64,
+    pub age_secs: u64,
+    pub heartbeat_secs: u64,
+    /// No update within the heartbeat
+    pub stale: bool,
+}
+
+impl PriceFreshness {
+    pub fn new(price_data: &PriceData, config: &TokenConfig, now: u64) -> Self {
+        let age_secs = now.saturating_sub(price_data.timestamp) / MICROS_PER_SECOND;
+        PriceFreshness {
+            token: price_data.token.clone(),
+            updated_at: price_data.timestamp,
+            age_secs,
+            heartbeat_secs: config.heartbeat_secs,
+            stale: age_secs > config.heartbeat_secs,
+        }
+    }
+}
+
+/// Spread of the submissions behind an aggregated price
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct PriceConfidence {
+    /// Standard deviation of the accepted prices
+    pub stddev: Price,
+    pub min: Price,
+    pub max: Price,
+    /// Standard deviation relative to the aggregated price
+    #[serde(with = "float_bits")]
+    pub dispersion: f64,
+    /// Number of accepted sources
+    pub sources: u32,
+}
+
+/// A published aggregate, kept per token to compute time-weighted averages
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct PricePoint {
+    pub price: Price,
+    /// VWAP of the round's submissions
+    pub vwap: Price,
+    /// Total volume reported in the round
+    #[serde(with = "float_bits")]
+    pub volume: f64,
+    pub timestamp: u64,
+}
+
+/// A supported price feed: a base asset priced in a quote asset
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct Asset {
+    /// Canonical feed name, used as the `token` of its prices
+    pub symbol: String,
+    pub base: String,
+    pub quote: String,
+    pub display_name: String,
+    /// Whether price submissions and new alerts are accepted
+    pub enabled: bool,
+    /// Aggregation settings, including the decimals of the published prices
+    pub config: TokenConfig,
+    /// Sources allowed to submit prices, or every provider if `None`
+    pub providers: Option<Vec<String>>,
+}
+
+impl Asset {
+    /// The asset's `BASE/QUOTE` pair, which resolves to it like its symbol
+    pub fn pair(&self) -> String {
+        format!("{}/{}", self.base, self.quote)
+    }
+
+    /// Whether `source` may submit prices for this asset
+    pub fn serves(&self, source: &str) -> bool {
+        self.providers
+            .as_ref()
+            .is_none_or(|providers| providers.iter().any(|provider| provider == source))
+    }
+}
+
+/// Canonical form of a token symbol or pair: feeds differing only in case or
+/// surrounding whitespace are the same
+pub fn normalize_symbol(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+/// Per-token aggregation settings
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, InputObject)]
+#[graphql(input_name = "TokenConfigInput")]
+pub struct TokenConfig {
+    /// Decimals of the token's published prices; submissions are rescaled to it
+    pub decimals: u8,
+    /// Window of the TWAP published with each aggregated price
+    pub twap_window_secs: u64,
+    /// Window of the rolling VWAP published with each aggregated price
+    pub vwap_window_secs: u64,
+    /// How submissions are weighted into the canonical price
+    pub weighting: WeightingStrategy,
+    /// How submissions deviating from the round median are detected
+    pub outlier_method: OutlierMethod,
+    /// Deviation, in MADs or standard deviations, beyond which a submission is rejected
+    #[serde(with = "float_bits")]
+    pub outlier_threshold: f64,
+    /// Relative deviation from the median below which a submission is never rejected
+    #[serde(with = "float_bits")]
+    pub outlier_min_deviation: f64,
+    /// Distinct sources a round needs to produce an answer, or all of the
+    /// token's providers when it has fewer
+    pub min_sources: u32,
+    /// Fraction of the registered providers whose submissions close a round early
+    #[serde(with = "float_bits")]
+    pub min_provider_fraction: f64,
+    /// Age after which a round is finalized with the submissions it has, or abandoned
+    pub max_round_age_secs: u64,
+    /// Which submission counts when a source submits twice in a round
+    pub duplicate_policy: DuplicatePolicy,
+    /// Oldest accepted submission, relative to the chain's clock
+    pub max_submission_age_secs: u64,
+    /// How far ahead of the chain's clock a submission may be dated
+    pub max_clock_drift_secs: u64,
+    /// Expected interval between updates; older prices are reported as stale
+    pub heartbeat_secs: u64,
+    /// Age after which published prices leave the history
+    pub history_retention_secs: u64,
+    /// Most published prices kept in the history
+    pub max_history_len: u32,
+}
+
+impl TokenConfig {
+    /// Distinct sources a round needs to produce an answer, given the token's
+    /// number of providers
+    pub fn required_sources(&self, provider_count: u64) -> usize {
+        (self.min_sources as usize).min(provider_count as usize).max(1)
+    }
+
+    /// Distinct sources that close a round before it times out
+    pub fn quorum(&self, provider_count: u64) -> usize {
+        let providers = (self.min_provider_fraction * provider_count as f64).ceil() as usize;
+        providers.max(self.required_sources(provider_count))
+    }
+
+    /// Check that the settings are usable
+    pub fn validate(&self) -> Result<(), OracleError> {
+        if self.decimals > MAX_PRICE_DECIMALS {
+            return Err(OracleError::InvalidConfig(format!(
+                "decimals must not exceed {}",
+                MAX_PRICE_DECIMALS
+            )));
+        }
+        for window_secs in [self.twap_window_secs, self.vwap_window_secs] {
+            if window_secs == 0 || window_secs > MAX_TWAP_WINDOW_SECS {
+                return Err(OracleError::InvalidConfig(format!(
+                    "TWAP/VWAP windows must be between 1 and {} seconds",
+                    MAX_TWAP_WINDOW_SECS
+                )));
+            }
+        }
+        if !(self.outlier_threshold.is_finite() && self.outlier_threshold > 0.0) {
+            return Err(OracleError::InvalidConfig(
+                "outlier threshold must be positive".to_string(),
+            ));
+        }
+        if !(self.outlier_min_deviation.is_finite() && self.outlier_min_deviation >= 0.0) {
+            return Err(OracleError::InvalidConfig(
+                "outlier minimum deviation must not be negative".to_string(),
+            ));
+        }
+        if self.min_sources == 0 {
+            return Err(OracleError::InvalidConfig(
+                "rounds need at least one source".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.min_provider_fraction) {
+            return Err(OracleError::InvalidConfig(
+                "minimum provider fraction must be between 0 and 1".to_string(),
+            ));
+        }
+        if self.max_round_age_secs == 0 {
+            return Err(OracleError::InvalidConfig(
+                "maximum round age must be positive".to_string(),
+            ));
+        }
+        if self.history_retention_secs == 0 || self.max_history_len == 0 {
+            return Err(OracleError::InvalidConfig(
+                "history retention must be positive".to_string(),
+            ));
+        }
+        if self.max_submission_age_secs == 0 || self.heartbeat_secs == 0 {
+            return Err(OracleError::InvalidConfig(
+                "submission age and heartbeat must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        TokenConfig {
+            decimals: DEFAULT_PRICE_DECIMALS,
+            twap_window_secs: DEFAULT_TWAP_WINDOW_SECS,
+            vwap_window_secs: DEFAULT_VWAP_WINDOW_SECS,
+            weighting: WeightingStrategy::default(),
+            outlier_method: OutlierMethod::default(),
+            outlier_threshold: 3.0,
+            outlier_min_deviation: 0.005,
+            min_sources: 3,
+            min_provider_fraction: 0.0,
+            max_round_age_secs: DEFAULT_MAX_ROUND_AGE_SECS,
+            duplicate_policy: DuplicatePolicy::default(),
+            max_submission_age_secs: DEFAULT_MAX_SUBMISSION_AGE_SECS,
+            max_clock_drift_secs: DEFAULT_MAX_CLOCK_DRIFT_SECS,
+            heartbeat_secs: DEFAULT_HEARTBEAT_SECS,
+            history_retention_secs: DEFAULT_HISTORY_RETENTION_SECS,
+            max_history_len: DEFAULT_MAX_HISTORY_LEN,
+        }
+    }
+}
+
+/// How repeated submissions from one source within a round are handled
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum DuplicatePolicy {
+    /// A resubmission replaces the source's earlier one
+    #[default]
+    LatestWins,
+    /// A resubmission is refused
+    FirstWins,
+}
+
+/// Statistic used to reject outlying submissions before aggregation
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum OutlierMethod {
+    /// Keep every valid submission
+    None,
+    /// Median absolute deviation around the round median
+    #[default]
+    Mad,
+    /// Standard deviations from the round median
+    ZScore,
+}
+
+/// Formula turning a source's reputation into its aggregation weight
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum WeightingStrategy {
+    /// Plain mean, every source counts the same
+    Equal,
+    /// Weight by accuracy only
+    Accuracy,
+    /// Weight by accuracy and uptime
+    AccuracyUptime,
+    /// Weight by accuracy, uptime and inverse latency (matches the off-chain aggregator)
+    #[default]
+    Reputation,
+}
+
+impl WeightingStrategy {
+    /// Aggregation weight of a source; sources without history get a neutral weight
+    pub fn weight(&self, reputation: Option<&OracleReputation>) -> f64 {
+        let Some(reputation) = reputation else {
+            return 1.0;
+        };
+        let latency_secs = reputation.latency_average as f64 / MICROS_PER_SECOND as f64;
+        match self {
+            WeightingStrategy::Equal => 1.0,
+            WeightingStrategy::Accuracy => reputation.accuracy,
+            WeightingStrategy::AccuracyUptime => reputation.accuracy * reputation.uptime,
+            WeightingStrategy::Reputation => {
+                reputation.accuracy * reputation.uptime / (1.0 + latency_secs)
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, InputObject)]
 #[graphql(input_name = "OracleInputInput")]
 pub struct OracleInput {
     pub source: String,
-    pub price: f64,
+    pub price: Price,
+    #[serde(default, with = "float_bits::option")]
+    pub volume: Option<f64>,
+    /// Delay between the submission's timestamp and its arrival on the aggregator
     pub latency: u64,
     pub timestamp: u64,
+    /// Excluded from the aggregate as invalid or an outlier
+    pub rejected: bool,
+}
+
+/// A round of submissions for one token, aggregated into a single answer
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct OracleRound {
+    /// Sequential per token, starting at 1
+    pub round_id: u64,
+    pub token: String,
+    /// When the first submission arrived
+    pub started_at: u64,
+    /// When the latest submission arrived
+    pub updated_at: u64,
+    /// When the round was closed, `None` while it is open
+    pub answered_at: Option<u64>,
+    pub status: RoundStatus,
+    /// Sources that submitted in this round
+    pub sources: Vec<String>,
+    /// One submission per source, in order of arrival
+    pub submissions: Vec<OracleInput>,
+    /// Aggregated price, `None` while open or if every submission was rejected
+    pub answer: Option<Price>,
+}
+
+impl OracleRound {
+    pub fn new(round_id: u64, token: String, started_at: u64) -> Self {
+        OracleRound {
+            round_id,
+            token,
+            started_at,
+            updated_at: started_at,
+            answered_at: None,
+            status: RoundStatus::Open,
+            sources: Vec::new(),
+            submissions: Vec::new(),
+            answer: None,
+        }
+    }
+
+    /// When the round times out under `config`
+    pub fn expires_at(&self, config: &TokenConfig) -> u64 {
+        self.started_at
+            .saturating_add(config.max_round_age_secs.saturating_mul(MICROS_PER_SECOND))
+    }
+
+    /// The round in Chainlink's `getRoundData` shape, if it was answered
+    pub fn round_data(&self) -> Option<RoundData> {
+        Some(RoundData {
+            round_id: self.round_id,
+            answer: self.answer?,
+            started_at: self.started_at,
+            updated_at: self.answered_at?,
+            answered_in_round: self.round_id,
+        })
+    }
+}
+
+/// Lifecycle of an aggregation round
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum RoundStatus {
+    /// Collecting submissions
+    Open,
+    /// Answered once its quorum was reached
+    Finalized,
+    /// Answered with the submissions it had when it timed out
+    TimedOut,
+    /// Closed without an answer
+    Abandoned,
+}
+
+/// Answer of a round, shaped like Chainlink's `latestRoundData` and `getRoundData`
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct RoundData {
+    pub round_id: u64,
+    pub answer: Price,
+    pub started_at: u64,
+    /// When the answer was computed
+    pub updated_at: u64,
+    pub answered_in_round: u64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, InputObject)]
 #[graphql(input_name = "CandleInput")]
 pub struct Candle {
-    pub open: f64,
-    pub high: f64,
-    pub low: f64,
-    pub close: f64,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    #[serde(with = "float_bits")]
     pub volume: f64,
     pub timestamp: u64,
 }
 
+/// Length of a candle. Candles of every interval are stored alike, keyed by
+/// interval, so adding one only needs a variant here.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Enum, Copy)]
 pub enum CandleInterval {
     OneSecond,
     OneMinute,
     OneHour,
     TwentyFourHour,
+    FiveMinute,
+    FifteenMinute,
+    FourHour,
+    OneWeek,
+}
+
+impl CandleInterval {
+    /// Every interval, shortest first
+    pub const ALL: [CandleInterval; 8] = [
+        CandleInterval::OneSecond,
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinute,
+        CandleInterval::FifteenMinute,
+        CandleInterval::OneHour,
+        CandleInterval::FourHour,
+        CandleInterval::TwentyFourHour,
+        CandleInterval::OneWeek,
+    ];
+
+    /// Length of one candle in seconds
+    pub fn duration_secs(&self) -> u64 {
+        match self {
+            CandleInterval::OneSecond => 1,
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinute => 5 * 60,
+            CandleInterval::FifteenMinute => 15 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::FourHour => 4 * 60 * 60,
+            CandleInterval::TwentyFourHour => 24 * 60 * 60,
+            CandleInterval::OneWeek => 7 * 24 * 60 * 60,
+        }
+    }
+
+    /// Length of one candle in microseconds
+    pub fn duration_micros(&self) -> u64 {
+        self.duration_secs() * MICROS_PER_SECOND
+    }
+
+    /// Interval with the given length, if there is one
+    pub fn from_secs(secs: u64) -> Option<CandleInterval> {
+        CandleInterval::ALL
+            .into_iter()
+            .find(|interval| interval.duration_secs() == secs)
+    }
+
+    /// Start of the candle containing `timestamp`. Weekly candles start on
+    /// Monday; the Unix epoch was a Thursday.
+    pub fn bucket_start(&self, timestamp: u64) -> u64 {
+        let offset = match self {
+            CandleInterval::OneWeek => 4 * 24 * 60 * 60 * MICROS_PER_SECOND,
+            _ => 0,
+        };
+        let Some(shifted) = timestamp.checked_sub(offset) else {
+            return 0;
+        };
+        shifted - shifted % self.duration_micros() + offset
+    }
+
+    /// The next shorter interval that tiles this one, which it is rolled up from
+    pub fn rollup_source(&self) -> Option<CandleInterval> {
+        match self {
+            CandleInterval::OneSecond => None,
+            CandleInterval::OneMinute => Some(CandleInterval::OneSecond),
+            CandleInterval::FiveMinute => Some(CandleInterval::OneMinute),
+            CandleInterval::FifteenMinute => Some(CandleInterval::FiveMinute),
+            CandleInterval::OneHour => Some(CandleInterval::FifteenMinute),
+            CandleInterval::FourHour => Some(CandleInterval::OneHour),
+            CandleInterval::TwentyFourHour => Some(CandleInterval::FourHour),
+            CandleInterval::OneWeek => Some(CandleInterval::TwentyFourHour),
+        }
+    }
+
+    /// Candles kept per token; older ones are evicted
+    pub fn max_candles(&self) -> usize {
+        match self {
+            CandleInterval::OneSecond => 24 * 60 * 60,
+            CandleInterval::OneMinute => 30 * 24 * 60,
+            CandleInterval::FiveMinute => 90 * 24 * 12,
+            CandleInterval::FifteenMinute => 180 * 24 * 4,
+            CandleInterval::OneHour => 365 * 24,
+            CandleInterval::FourHour => 2 * 365 * 6,
+            CandleInterval::TwentyFourHour => 10 * 365,
+            CandleInterval::OneWeek => 20 * 52,
+        }
+    }
+}
+
+/// Storage key of the candle starting at `bucket_start`.
+///
+/// `u128` keys are stored big-endian, so inverting the start makes iteration
+/// go from the newest candle to the oldest.
+pub fn candle_key(bucket_start: u64) -> u128 {
+    u128::from(u64::MAX - bucket_start)
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, InputObject)]
@@ -110,7 +704,7 @@ pub struct AlertConfig {
     pub id: String,
     pub token: String,
     pub threshold_type: ThresholdType,
-    pub threshold_value: f64,
+    pub threshold_value: Price,
     pub active: bool,
     pub created_at: u64,
 }
@@ -121,15 +715,202 @@ pub enum ThresholdType {
     Below,
 }
 
+/// A registered oracle provider
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct ProviderRecord {
+    pub source: String,
+    /// Chain the provider submits from
+    pub chain_id: ChainId,
+    pub status: ProviderStatus,
+    /// Chain the provider was rotated away from, accepted until `grace_until`
+    pub previous_chain: Option<ChainId>,
+    pub grace_until: u64,
+}
+
+impl ProviderRecord {
+    pub fn new(source: String, chain_id: ChainId) -> Self {
+        ProviderRecord {
+            source,
+            chain_id,
+            status: ProviderStatus::Active,
+            previous_chain: None,
+            grace_until: 0,
+        }
+    }
+
+    /// The provider's chain and, after a rotation, its previous one
+    pub fn chains(&self) -> Vec<ChainId> {
+        [Some(self.chain_id), self.previous_chain].into_iter().flatten().collect()
+    }
+
+    /// Whether submissions from `chain_id` are accepted for this provider at `now`
+    pub fn accepts(&self, chain_id: ChainId, now: u64) -> bool {
+        chain_id == self.chain_id || (self.previous_chain == Some(chain_id) && now < self.grace_until)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum ProviderStatus {
+    #[default]
+    Active,
+    /// Submissions are refused until the provider is resumed
+    Suspended,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
 pub struct OracleReputation {
     pub source: String,
+    /// Moving average of how close submissions are to the round answers,
+    /// from 0 (off by `MAX_ACCURACY_DEVIATION` or more) to 1 (exact)
+    #[serde(with = "float_bits")]
     pub accuracy: f64,
+    /// Moving average of how long after a round started its submissions arrived
     pub latency_average: u64,
+    /// Moving average of the share of the source's rounds it submitted to
+    #[serde(with = "float_bits")]
     pub uptime: f64,
+    /// Moving average of the squared relative deviation from the round answers
+    #[serde(with = "float_bits")]
     pub variance: f64,
     pub total_updates: u64,
+    /// Submissions excluded from aggregation as invalid or outliers
+    pub rejected_updates: u64,
+    /// Rounds the source was scored in
+    pub rounds: u64,
+    /// Scored rounds the source submitted to
+    pub participation: u64,
     pub last_update: u64,
+    /// Whether the source's submissions are left out of aggregation for
+    /// falling below the `ReputationPolicy` floors
+    pub quarantined: bool,
+    /// When a round last scored the source, the start of the next decay
+    pub scored_at: u64,
+}
+
+impl OracleReputation {
+    /// Reputation of a source without history, trusted until it misbehaves
+    pub fn new(source: String) -> Self {
+        OracleReputation {
+            source,
+            accuracy: 1.0,
+            latency_average: 0,
+            uptime: 1.0,
+            variance: 0.0,
+            total_updates: 0,
+            rejected_updates: 0,
+            rounds: 0,
+            participation: 0,
+            last_update: 0,
+            quarantined: false,
+            scored_at: 0,
+        }
+    }
+
+    /// Move accuracy and uptime back towards 1 and variance towards 0 for the
+    /// time elapsed since the last scoring, halving the gap every `half_life_secs`
+    pub fn decay(&mut self, now: u64, half_life_secs: u64) {
+        if self.scored_at == 0 || half_life_secs == 0 || now <= self.scored_at {
+            return;
+        }
+        let elapsed_secs = (now - self.scored_at) as f64 / MICROS_PER_SECOND as f64;
+        let remaining = 0.5f64.powf(elapsed_secs / half_life_secs as f64);
+        self.accuracy = 1.0 - (1.0 - self.accuracy) * remaining;
+        self.uptime = 1.0 - (1.0 - self.uptime) * remaining;
+        self.variance *= remaining;
+    }
+}
+
+/// Accuracy score of a submission off the round answer by a relative `deviation`
+pub fn accuracy_score(deviation: f64) -> f64 {
+    1.0 - (deviation / MAX_ACCURACY_DEVIATION).min(1.0)
+}
+
+/// A provider's showing in one scored round, and its reputation afterwards
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
+pub struct ReputationSample {
+    pub token: String,
+    pub round_id: u64,
+    /// When the round was scored
+    pub timestamp: u64,
+    /// Whether the provider submitted to the round
+    pub participated: bool,
+    /// The provider's submission, rescaled to the token's decimals
+    pub price: Option<Price>,
+    pub answer: Option<Price>,
+    /// Relative deviation of the submission from the answer
+    #[serde(default, with = "float_bits::option")]
+    pub deviation: Option<f64>,
+    /// Time from the round's start to the submission's arrival
+    pub latency: Option<u64>,
+    #[serde(with = "float_bits")]
+    pub accuracy: f64,
+    #[serde(with = "float_bits")]
+    pub uptime: f64,
+    #[serde(with = "float_bits")]
+    pub variance: f64,
+    pub latency_average: u64,
+    pub quarantined: bool,
+}
+
+/// When providers are quarantined for a poor reputation, and how they recover
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, InputObject)]
+#[graphql(input_name = "ReputationPolicyInput")]
+pub struct ReputationPolicy {
+    /// Accuracy below which a provider is quarantined
+    #[serde(with = "float_bits")]
+    pub min_accuracy: f64,
+    /// Uptime below which a provider is quarantined
+    #[serde(with = "float_bits")]
+    pub min_uptime: f64,
+    /// How far above both floors a quarantined provider must get to be released
+    #[serde(with = "float_bits")]
+    pub release_margin: f64,
+    /// Submissions a provider makes before it can be quarantined
+    pub min_updates: u64,
+    /// Time over which half of a provider's accuracy and uptime shortfall is
+    /// forgiven; 0 disables decay
+    pub recovery_half_life_secs: u64,
+}
+
+impl ReputationPolicy {
+    /// Whether `reputation` should be quarantined, given whether it already is
+    pub fn quarantines(&self, reputation: &OracleReputation) -> bool {
+        if reputation.total_updates < self.min_updates {
+            return false;
+        }
+        let margin = if reputation.quarantined { self.release_margin } else { 0.0 };
+        reputation.accuracy < self.min_accuracy + margin || reputation.uptime < self.min_uptime + margin
+    }
+
+    /// Check that the policy is usable
+    pub fn validate(&self) -> Result<(), OracleError> {
+        let fractions = [self.min_accuracy, self.min_uptime, self.release_margin];
+        if !fractions.iter().all(|fraction| (0.0..=1.0).contains(fraction)) {
+            return Err(OracleError::InvalidConfig(
+                "reputation floors and release margin must be between 0 and 1".to_string(),
+            ));
+        }
+        // Accuracy and uptime never exceed 1, so a higher release bar would
+        // keep quarantined providers out for good
+        if self.min_accuracy + self.release_margin > 1.0 || self.min_uptime + self.release_margin > 1.0 {
+            return Err(OracleError::InvalidConfig(
+                "reputation floors plus the release margin must not exceed 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ReputationPolicy {
+    fn default() -> Self {
+        ReputationPolicy {
+            min_accuracy: 0.5,
+            min_uptime: 0.5,
+            release_margin: 0.1,
+            min_updates: 10,
+            recovery_half_life_secs: DEFAULT_RECOVERY_HALF_LIFE_SECS,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, SimpleObject)]
@@ -137,51 +918,92 @@ pub struct AggregatedStats {
     pub total_queries: u64,
     pub avg_latency: u64,
     pub active_oracles: u64,
+    #[serde(with = "float_bits")]
     pub network_uptime: f64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum OracleMessage {
-    /// Provider submits price to aggregator
+    /// Provider submits price to aggregator; must originate from the chain
+    /// registered for `source`
     SubmitPrice {
         token: String,
-        price: f64,
+        price: Price,
         source: String,
         timestamp: u64,
+        #[serde(default, with = "float_bits::option")]
+        volume: Option<f64>,
     },
     /// Request price aggregation from providers
     RequestPrice {
         token: String,
         requester: ChainId,
     },
-    /// Aggregation result sent back to requester
+    /// Aggregation result sent back to requester, accepted from the aggregator chain only
     AggregationResult {
-        token: String,
-        aggregated_price: f64,
-        median: f64,
-        twap: f64,
-        vwap: f64,
+        price_data: Box<PriceData>,
         oracle_inputs: Vec<OracleInput>,
-        timestamp: u64,
     },
     /// Register provider chain
     RegisterProvider {
         provider_chain: ChainId,
         source_name: String,
     },
+    /// Forward a provider's removal from the master chain
+    DeregisterProvider {
+        source_name: String,
+    },
+    /// Forward a provider's suspension from the master chain
+    SuspendProvider {
+        source_name: String,
+    },
+    /// Forward a provider's resumption from the master chain
+    ResumeProvider {
+        source_name: String,
+    },
+    /// Forward a provider's new chain from the master chain
+    RotateProvider {
+        source_name: String,
+        new_chain: ChainId,
+        grace_until: u64,
+    },
+    /// Forward the reputation policy from the master chain
+    ConfigureReputation { policy: ReputationPolicy },
+    /// Forward a registered asset from the master chain
+    RegisterAsset { asset: Box<Asset> },
+    /// Forward token settings from the master chain
+    ConfigureToken {
+        token: String,
+        config: TokenConfig,
+    },
+    /// Forward a backfilled candle from the master chain
+    UpdateCandle {
+        token: String,
+        interval: CandleInterval,
+        candle: Candle,
+    },
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum OracleEvent {
     /// Price update event (streamed to subscribers)
     PriceUpdate {
         token: String,
-        price: f64,
-        aggregated_price: f64,
-        median: f64,
-        twap: f64,
-        vwap: f64,
+        price: Price,
+        aggregated_price: Price,
+        median: Price,
+        twap: Price,
+        twap_window_secs: u64,
+        vwap: Price,
+        vwap_window_secs: u64,
+        round_vwap: Price,
+        #[serde(with = "float_bits")]
+        volume: f64,
+        mean: Price,
+        weighting: WeightingStrategy,
+        confidence: PriceConfidence,
         oracle_breakdown: Vec<OracleInput>,
+        round_id: u64,
         timestamp: u64,
     },
     /// New oracle provider registered
@@ -189,13 +1011,134 @@ pub enum OracleEvent {
         source: String,
         chain_id: ChainId,
     },
+    /// Oracle provider removed
+    ProviderDeregistered {
+        source: String,
+        chain_id: ChainId,
+    },
+    /// Oracle provider's submissions refused until it is resumed
+    ProviderSuspended {
+        source: String,
+        chain_id: ChainId,
+    },
+    /// Suspended oracle provider accepted again
+    ProviderResumed {
+        source: String,
+        chain_id: ChainId,
+    },
+    /// Oracle provider moved to a new chain
+    ProviderRotated {
+        source: String,
+        previous_chain: Option<ChainId>,
+        chain_id: ChainId,
+        grace_until: u64,
+    },
+    /// Oracle provider left out of aggregation for a poor reputation
+    ProviderQuarantined {
+        source: String,
+        #[serde(with = "float_bits")]
+        accuracy: f64,
+        #[serde(with = "float_bits")]
+        uptime: f64,
+    },
+    /// Quarantined oracle provider's reputation recovered
+    ProviderReleased {
+        source: String,
+        #[serde(with = "float_bits")]
+        accuracy: f64,
+        #[serde(with = "float_bits")]
+        uptime: f64,
+    },
     /// Alert triggered
     AlertTriggered {
         user_id: String,
         alert_id: String,
         token: String,
-        price: f64,
+        price: Price,
+    },
+    /// Round closed with an answer, on quorum or on timeout
+    RoundFinalized {
+        token: String,
+        round_id: u64,
+        answer: Price,
+        sources: Vec<String>,
+        status: RoundStatus,
+    },
+    /// Round closed without an answer
+    RoundAbandoned {
+        token: String,
+        round_id: u64,
+        sources: Vec<String>,
     },
+    /// A candle's interval ended; it will not change anymore
+    CandleClosed {
+        token: String,
+        interval: CandleInterval,
+        candle: Candle,
+    },
+}
+
+impl OracleEvent {
+    pub fn kind(&self) -> OracleEventKind {
+        match self {
+            OracleEvent::PriceUpdate { .. } => OracleEventKind::PriceUpdate,
+            OracleEvent::ProviderRegistered { .. } => OracleEventKind::ProviderRegistered,
+            OracleEvent::ProviderDeregistered { .. } => OracleEventKind::ProviderDeregistered,
+            OracleEvent::ProviderSuspended { .. } => OracleEventKind::ProviderSuspended,
+            OracleEvent::ProviderResumed { .. } => OracleEventKind::ProviderResumed,
+            OracleEvent::ProviderRotated { .. } => OracleEventKind::ProviderRotated,
+            OracleEvent::ProviderQuarantined { .. } => OracleEventKind::ProviderQuarantined,
+            OracleEvent::ProviderReleased { .. } => OracleEventKind::ProviderReleased,
+            OracleEvent::AlertTriggered { .. } => OracleEventKind::AlertTriggered,
+            OracleEvent::RoundFinalized { .. } => OracleEventKind::RoundFinalized,
+            OracleEvent::RoundAbandoned { .. } => OracleEventKind::RoundAbandoned,
+            OracleEvent::CandleClosed { .. } => OracleEventKind::CandleClosed,
+        }
+    }
+
+    /// Token the event is about, if any
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            OracleEvent::PriceUpdate { token, .. }
+            | OracleEvent::AlertTriggered { token, .. }
+            | OracleEvent::RoundFinalized { token, .. }
+            | OracleEvent::RoundAbandoned { token, .. }
+            | OracleEvent::CandleClosed { token, .. } => Some(token),
+            OracleEvent::ProviderRegistered { .. }
+            | OracleEvent::ProviderDeregistered { .. }
+            | OracleEvent::ProviderSuspended { .. }
+            | OracleEvent::ProviderResumed { .. }
+            | OracleEvent::ProviderRotated { .. }
+            | OracleEvent::ProviderQuarantined { .. }
+            | OracleEvent::ProviderReleased { .. } => None,
+        }
+    }
+}
+
+/// Variant of an `OracleEvent`, for filtering the event log
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Enum, PartialEq, Eq)]
+pub enum OracleEventKind {
+    PriceUpdate,
+    ProviderRegistered,
+    AlertTriggered,
+    RoundFinalized,
+    RoundAbandoned,
+    CandleClosed,
+    ProviderDeregistered,
+    ProviderSuspended,
+    ProviderResumed,
+    ProviderRotated,
+    ProviderQuarantined,
+    ProviderReleased,
+}
+
+/// An emitted event as kept in the chain's event log
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OracleEventRecord {
+    /// Position in the chain's event stream, starting at 0
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub event: OracleEvent,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -203,3 +1146,148 @@ pub struct OracleParameters {
     pub master_chain: ChainId,
     pub aggregator_chain: ChainId,
 }
+
+/// Outcome of a successful operation
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum OracleResponse {
+    /// The operation was applied
+    Ok,
+    /// The price submission was sent on to the aggregator chain
+    Forwarded,
+    /// The price submission is waiting for the round to fill up
+    Pending { submissions: u32 },
+    /// The price submission completed a round and this price was published
+    Published { price: Price },
+    /// The round was closed without an answer
+    Abandoned { round_id: u64 },
+}
+
+/// Reasons an oracle request is refused. Operations return these as their
+/// response; rejected messages are logged and dropped.
+#[derive(Clone, Debug, Deserialize, Serialize, Error, PartialEq, Eq)]
+pub enum OracleError {
+    #[error("chain {0} is not authorized to perform this operation")]
+    Unauthorized(ChainId),
+    #[error("source {0:?} is not a registered provider")]
+    UnknownProvider(String),
+    #[error("source {source_name:?} is already registered to chain {chain_id}")]
+    ProviderExists { source_name: String, chain_id: ChainId },
+    #[error("source {source_name:?} does not serve token {token:?}")]
+    SourceNotAllowed { source_name: String, token: String },
+    #[error("source {source_name:?} is the only provider of token {token:?}")]
+    LastTokenProvider { source_name: String, token: String },
+    #[error("source {0:?} is suspended")]
+    ProviderSuspended(String),
+    #[error("source {source_name:?} is registered to chain {expected}, not {actual}")]
+    UnauthorizedSource {
+        source_name: String,
+        expected: ChainId,
+        actual: ChainId,
+    },
+    #[error("token {0:?} is not a registered asset")]
+    UnknownToken(String),
+    #[error("asset {0:?} is disabled")]
+    AssetDisabled(String),
+    #[error("invalid asset: {0}")]
+    InvalidAsset(String),
+    #[error("timestamp {timestamp} is not newer than the latest price at {latest}")]
+    StaleTimestamp { timestamp: u64, latest: u64 },
+    #[error("timestamp {timestamp} is outside the accepted range {earliest}..={latest}")]
+    TimestampOutOfRange {
+        timestamp: u64,
+        earliest: u64,
+        latest: u64,
+    },
+    #[error("price {0} must be positive with at most {max} decimals", max = MAX_PRICE_DECIMALS)]
+    InvalidPrice(Price),
+    #[error("invalid token configuration: {0}")]
+    InvalidConfig(String),
+    #[error("invalid candle: {0}")]
+    InvalidCandle(String),
+    #[error("alert {0:?} not found")]
+    AlertNotFound(String),
+    #[error("source {source_name:?} already submitted in round {round_id}")]
+    DuplicateSubmission { source_name: String, round_id: u64 },
+    #[error("token {0:?} has no open round")]
+    NoOpenRound(String),
+    #[error("round {round_id} is open until {expires_at}")]
+    RoundNotExpired { round_id: u64, expires_at: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60 * MICROS_PER_SECOND;
+
+    /// 2024-01-01 00:00 UTC, a Monday
+    const MONDAY: u64 = 1_704_067_200 * MICROS_PER_SECOND;
+
+    #[test]
+    fn weekly_candles_start_on_monday() {
+        let week = CandleInterval::OneWeek;
+        assert_eq!(week.bucket_start(MONDAY), MONDAY);
+        assert_eq!(week.bucket_start(MONDAY + 2 * DAY + 1), MONDAY);
+        assert_eq!(week.bucket_start(MONDAY + 7 * DAY - 1), MONDAY);
+        assert_eq!(week.bucket_start(MONDAY + 7 * DAY), MONDAY + 7 * DAY);
+        assert_eq!(week.bucket_start(MONDAY - 1), MONDAY - 7 * DAY);
+        // The first Monday after the epoch, and the partial week before it
+        assert_eq!(week.bucket_start(5 * DAY), 4 * DAY);
+        assert_eq!(week.bucket_start(DAY), 0);
+    }
+
+    #[test]
+    fn shorter_candles_start_on_their_duration() {
+        let half_past_ten = MONDAY + (10 * 60 + 30) * 60 * MICROS_PER_SECOND;
+        let ten = MONDAY + 10 * 60 * 60 * MICROS_PER_SECOND;
+        assert_eq!(CandleInterval::OneHour.bucket_start(half_past_ten), ten);
+        assert_eq!(CandleInterval::FourHour.bucket_start(half_past_ten), MONDAY + 8 * 60 * 60 * MICROS_PER_SECOND);
+        assert_eq!(CandleInterval::TwentyFourHour.bucket_start(half_past_ten), MONDAY);
+        assert_eq!(CandleInterval::OneSecond.bucket_start(ten + 999_999), ten);
+    }
+
+    #[test]
+    fn rollup_sources_tile_their_targets() {
+        for interval in CandleInterval::ALL {
+            if let Some(source) = interval.rollup_source() {
+                assert_eq!(interval.duration_secs() % source.duration_secs(), 0, "{:?}", interval);
+            }
+        }
+    }
+
+    #[test]
+    fn candle_keys_order_newest_first() {
+        assert!(candle_key(MONDAY + DAY) < candle_key(MONDAY));
+    }
+
+    #[test]
+    fn default_quorum_is_up_to_three_providers() {
+        let config = TokenConfig::default();
+        assert_eq!(config.quorum(0), 1);
+        assert_eq!(config.quorum(2), 2);
+        assert_eq!(config.required_sources(2), 2);
+        assert_eq!(config.quorum(10), 3);
+        let config = TokenConfig {
+            min_provider_fraction: 0.5,
+            ..TokenConfig::default()
+        };
+        assert_eq!(config.quorum(10), 5);
+        assert_eq!(config.required_sources(10), 3);
+    }
+
+    #[test]
+    fn release_bar_must_be_reachable() {
+        assert!(ReputationPolicy::default().validate().is_ok());
+        let policy = ReputationPolicy {
+            min_accuracy: 0.95,
+            ..ReputationPolicy::default()
+        };
+        assert!(policy.validate().is_err());
+        let policy = ReputationPolicy {
+            min_uptime: 0.9,
+            release_margin: 0.2,
+            ..ReputationPolicy::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Fixed-point price representation.
+
+use std::{cmp::Ordering, fmt};
+
+use async_graphql::{InputObject, SimpleObject};
+use serde::{Deserialize, Serialize};
+
+/// Decimals used for tokens without explicit configuration (as Chainlink USD feeds)
+pub const DEFAULT_PRICE_DECIMALS: u8 = 8;
+
+/// Largest supported number of decimals
+pub const MAX_PRICE_DECIMALS: u8 = 18;
+
+/// Fixed-point price worth `mantissa * 10^-decimals`.
+///
+/// Prices compare by value, so `Price::new(150, 2) == Price::new(1500, 3)`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, SimpleObject, InputObject)]
+#[graphql(input_name = "PriceInput")]
+pub struct Price {
+    pub mantissa: u64,
+    pub decimals: u8,
+}
+
+impl Price {
+    pub fn new(mantissa: u64, decimals: u8) -> Self {
+        Price { mantissa, decimals }
+    }
+
+    pub fn zero(decimals: u8) -> Self {
+        Price::new(0, decimals)
+    }
+
+    /// Whether this is a usable oracle price: positive, with supported decimals
+    pub fn is_valid(&self) -> bool {
+        self.mantissa > 0 && self.decimals <= MAX_PRICE_DECIMALS
+    }
+
+    /// The same value expressed with `decimals` decimals.
+    ///
+    /// Rounds half up when dropping digits. Returns `None` if either scale is
+    /// unsupported or the mantissa would overflow.
+    pub fn rescale(&self, decimals: u8) -> Option<Price> {
+        if self.decimals > MAX_PRICE_DECIMALS || decimals > MAX_PRICE_DECIMALS {
+            return None;
+        }
+        let mantissa = match decimals.cmp(&self.decimals) {
+            Ordering::Equal => self.mantissa,
+            Ordering::Greater => {
+                let factor = 10u64.pow(u32::from(decimals - self.decimals));
+                self.mantissa.checked_mul(factor)?
+            }
+            Ordering::Less => {
+                let divisor = 10u128.pow(u32::from(self.decimals - decimals));
+                div_round(u128::from(self.mantissa), divisor) as u64
+            }
+        };
+        Some(Price::new(mantissa, decimals))
+    }
+
+    /// Closest fixed-point value to a floating-point price, for converting
+    /// off-chain data. Returns `None` for negative, non-finite or too large values.
+    pub fn from_f64(value: f64, decimals: u8) -> Option<Price> {
+        if !value.is_finite() || value < 0.0 || decimals > MAX_PRICE_DECIMALS {
+            return None;
+        }
+        let scaled = (value * 10f64.powi(i32::from(decimals))).round();
+        if scaled >= u64::MAX as f64 {
+            return None;
+        }
+        Some(Price::new(scaled as u64, decimals))
+    }
+
+    /// Approximate floating-point value, for ratios and display only
+    pub fn to_f64(&self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(i32::from(self.decimals))
+    }
+
+    /// Mantissa at the largest supported scale, for comparisons
+    fn normalized(&self) -> u128 {
+        let shift = MAX_PRICE_DECIMALS.saturating_sub(self.decimals);
+        u128::from(self.mantissa) * 10u128.pow(u32::from(shift))
+    }
+}
+
+/// `numerator / denominator`, rounding half up
+pub(crate) fn div_round(numerator: u128, denominator: u128) -> u128 {
+    (numerator + denominator / 2) / denominator
+}
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.normalized().cmp(&other.normalized())
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.decimals == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+        if self.decimals > MAX_PRICE_DECIMALS {
+            return write!(f, "{}e-{}", self.mantissa, self.decimals);
+        }
+        let divisor = 10u128.pow(u32::from(self.decimals));
+        let mantissa = u128::from(self.mantissa);
+        write!(
+            f,
+            "{}.{:0width$}",
+            mantissa / divisor,
+            mantissa % divisor,
+            width = usize::from(self.decimals)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescale_rounds_half_up_when_dropping_digits() {
+        let down = |mantissa, decimals| Price::new(mantissa, 3).rescale(decimals).map(|price| price.mantissa);
+        assert_eq!(down(12_345, 2), Some(1_235));
+        assert_eq!(down(12_344, 2), Some(1_234));
+        assert_eq!(down(500, 0), Some(1));
+        assert_eq!(down(499, 0), Some(0));
+        assert_eq!(down(12_345, 3), Some(12_345));
+    }
+
+    #[test]
+    fn rescale_up_is_exact_and_refuses_overflow() {
+        let up = Price::new(12_345, 2).rescale(5).unwrap();
+        assert_eq!((up.mantissa, up.decimals), (12_345_000, 5));
+        assert_eq!(Price::new(u64::MAX, 0).rescale(1), None);
+        assert_eq!(Price::new(1, MAX_PRICE_DECIMALS + 1).rescale(2), None);
+        assert_eq!(Price::new(1, 2).rescale(MAX_PRICE_DECIMALS + 1), None);
+    }
+
+    #[test]
+    fn prices_compare_by_value() {
+        assert_eq!(Price::new(150, 2), Price::new(1_500, 3));
+        assert!(Price::new(151, 2) > Price::new(1_500, 3));
+        assert!(Price::new(1, 18) < Price::new(1, 17));
+    }
+
+    #[test]
+    fn validity_needs_a_positive_mantissa_and_supported_decimals() {
+        assert!(Price::new(1, 8).is_valid());
+        assert!(!Price::zero(8).is_valid());
+        assert!(!Price::new(1, MAX_PRICE_DECIMALS + 1).is_valid());
+    }
+
+    #[test]
+    fn from_f64_rounds_to_the_closest_mantissa() {
+        assert_eq!(Price::from_f64(12.5, 0).map(|price| price.mantissa), Some(13));
+        assert_eq!(Price::from_f64(0.123_456_789, 8).map(|price| price.mantissa), Some(12_345_679));
+        assert_eq!(Price::from_f64(-1.0, 8), None);
+        assert_eq!(Price::from_f64(f64::NAN, 8), None);
+        assert_eq!(Price::from_f64(1e30, 8), None);
+    }
+
+    #[test]
+    fn display_pads_the_fraction() {
+        assert_eq!(Price::new(123_456, 4).to_string(), "12.3456");
+        assert_eq!(Price::new(5, 3).to_string(), "0.005");
+        assert_eq!(Price::new(42, 0).to_string(), "42");
+    }
+}
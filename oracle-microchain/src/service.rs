@@ -2,22 +2,35 @@
 
 mod state;
 
-use std::sync::Arc;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
 
-use async_graphql::{EmptySubscription, Object, Schema};
+use async_graphql::{
+    connection::{query, Connection, CursorType, Edge},
+    Context, EmptySubscription, Enum, Json, Object, OutputType, Schema, SimpleObject,
+};
 use linera_sdk::{
-    abi::WithServiceAbi, 
+    abi::WithServiceAbi, bcs,
     graphql::GraphQLMutationRoot,
-    linera_base_types::ChainId,
-    Service, ServiceRuntime, views::View
+    Service, ServiceRuntime, views::{MapView, View, ViewError}
 };
+use serde::{de::DeserializeOwned, Serialize};
 use oracle_microchain::{
-    AggregatedStats, AlertConfig, Candle, CandleInterval, OracleAbi, OracleReputation, 
-    Operation, PriceData,
+    aggregation::{rolling_volume_weighted_average, time_weighted_average},
+    accuracy_score, candle_key, normalize_symbol, AggregatedStats, AlertConfig, Asset, Candle, CandleInterval, OracleAbi,
+    OracleEvent, OracleEventKind, OracleEventRecord, OracleInput, OracleReputation, OracleRound, Operation, Price,
+    PriceConfidence, PriceData, PriceFreshness, ProviderRecord, ReputationPolicy, ReputationSample, RoundData,
+    TokenConfig, MICROS_PER_SECOND,
 };
 
 use self::state::OracleState;
 
+/// Page size of list queries given neither `first` nor `last`
+const DEFAULT_PAGE_SIZE: usize = 100;
+
 pub struct OracleService {
     state: Arc<OracleState>,
     runtime: Arc<ServiceRuntime<Self>>,
@@ -49,6 +62,8 @@ impl Service for OracleService {
                 runtime: self.runtime.clone(),
             },
             Operation::mutation_root(self.runtime.clone()),
+            // The service is instantiated per query, so it can't hold subscriptions:
+            // clients subscribe to the node's `notifications` and read `eventsSince`
             EmptySubscription,
         )
         .finish();
@@ -63,73 +78,213 @@ struct QueryRoot {
 
 #[Object]
 impl QueryRoot {
-    /// Get latest price for any token
-    async fn latest_price(&self) -> Option<PriceData> {
-        self.state.latest_price.get().clone()
+    /// Get the latest price of a token, or of the most recently updated token
+    /// if none is given
+    async fn latest_price(&self, token: Option<String>) -> Option<PriceData> {
+        match token {
+            Some(token) => {
+                let token = self.symbol(&token).await;
+                self.state.prices.get(&token).await.ok().flatten()
+            }
+            None => self.state.recently_updated(1).await.ok()?.pop(),
+        }
+    }
+
+    /// Get the latest prices of several tokens, in the order asked, with
+    /// null for tokens without a price
+    async fn latest_prices(&self, tokens: Vec<String>) -> Vec<Option<PriceData>> {
+        let mut symbols = Vec::with_capacity(tokens.len());
+        for token in &tokens {
+            symbols.push(self.symbol(token).await);
+        }
+        self.state
+            .prices
+            .multi_get(&symbols)
+            .await
+            .unwrap_or_else(|_| vec![None; symbols.len()])
+    }
+
+    /// Get the latest prices of the most recently updated tokens, newest first
+    async fn recently_updated(&self, limit: Option<i32>) -> Vec<PriceData> {
+        let limit = limit.map_or(DEFAULT_PAGE_SIZE, |limit| limit.max(0) as usize);
+        self.state.recently_updated(limit).await.unwrap_or_default()
     }
 
     /// Get price for specific token
     async fn price(&self, token: String) -> Option<PriceData> {
+        let token = self.symbol(&token).await;
         match self.state.prices.get(&token).await {
             Ok(Some(data)) => Some(data),
             _ => None,
         }
     }
 
-    /// Get all stored prices
-    async fn all_prices(&self) -> Vec<PriceData> {
-        let mut prices = Vec::new();
-        let _ = self
-            .state
-            .prices
-            .for_each_index_value(|_key, value| {
-                prices.push(value.into_owned());
-                Ok(())
-            })
-            .await;
-        prices
+    /// Get all stored prices, paginated by token
+    async fn all_prices(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<String, PriceData, TotalCount>> {
+        let with_total = wants_total_count(ctx);
+        query(after, before, first, last, |after, before, first, last| {
+            map_connection(&self.state.prices, after, before, first, last, with_total)
+        })
+        .await
     }
 
-    /// Get historical prices for a token (last N entries)
+    /// Get historical prices for a token (last N entries, newest first)
     async fn price_history(&self, token: String, limit: Option<i32>) -> Vec<PriceData> {
-        // In a real implementation, we'd store a time-series
-        // For now, return the single stored price
-        match self.state.prices.get(&token).await {
-            Ok(Some(data)) => vec![data],
-            _ => vec![],
+        let limit = limit.unwrap_or(100).max(0) as usize;
+        let token = self.symbol(&token).await;
+        self.state.recent_history(&token, limit).await.unwrap_or_default()
+    }
+
+    /// Get historical prices for a token published between `start` and `end`
+    /// (inclusive, defaulting to now), oldest first
+    async fn price_history_range(&self, token: String, start: u64, end: Option<u64>) -> Vec<PriceData> {
+        let end = end.unwrap_or_else(|| self.runtime.system_time().micros());
+        let token = self.symbol(&token).await;
+        self.state
+            .history_range(&token, start, end)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Get historical prices for a token between `start` and `end`, keeping the
+    /// last price of every `interval_secs` bucket, oldest first
+    async fn downsampled_price_history(
+        &self,
+        token: String,
+        start: u64,
+        end: Option<u64>,
+        interval_secs: u64,
+    ) -> Vec<PriceData> {
+        let end = end.unwrap_or_else(|| self.runtime.system_time().micros());
+        let interval = interval_secs.max(1).saturating_mul(MICROS_PER_SECOND);
+        let token = self.symbol(&token).await;
+        let prices = self
+            .state
+            .history_range(&token, start, end)
+            .await
+            .unwrap_or_default();
+
+        let mut samples: Vec<PriceData> = Vec::new();
+        let mut last_bucket = None;
+        for price_data in prices {
+            let bucket = (price_data.timestamp - start) / interval;
+            if last_bucket == Some(bucket) {
+                samples.pop();
+            }
+            last_bucket = Some(bucket);
+            samples.push(price_data);
         }
+        samples
     }
 
-    /// Get OHLC candles for specific interval
+    /// Get OHLC candles for specific interval, newest first, paginated by
+    /// candle start
+    #[allow(clippy::too_many_arguments)]
     async fn candles(
         &self,
+        ctx: &Context<'_>,
         token: String,
-        interval: String,
-        limit: Option<i32>,
-    ) -> Vec<Candle> {
-        let map_view = match interval.as_str() {
-            "1s" => &self.state.candles_1s,
-            "1m" => &self.state.candles_1m,
-            "1h" => &self.state.candles_1h,
-            "24h" => &self.state.candles_24h,
-            _ => return vec![],
-        };
-
-        match map_view.get(&token).await {
-            Ok(Some(candles)) => {
-                let limit = limit.unwrap_or(100) as usize;
-                candles.into_iter().rev().take(limit).collect()
+        interval: CandleInterval,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<u64, Candle, TotalCount>> {
+        let token = self.symbol(&token).await;
+        let with_total = wants_total_count(ctx);
+        query(after, before, first, last, |after: Option<u64>, before: Option<u64>, first, last| async move {
+            let mut selection = PageSelection::new(after.map(candle_key), before.map(candle_key), first, last);
+            if let Some(legacy) = self.state.legacy_candles(&token, interval).await? {
+                let mut legacy: BTreeMap<u128, Candle> = legacy
+                    .into_iter()
+                    .map(|candle| (candle_key(candle.timestamp), candle))
+                    .collect();
+                let total = legacy.len();
+                for key in legacy.keys() {
+                    if !selection.push(*key) {
+                        break;
+                    }
+                }
+                let page: Vec<_> = selection
+                    .keys
+                    .iter()
+                    .filter_map(|key| legacy.remove(key))
+                    .map(|candle| (candle.timestamp, candle))
+                    .collect();
+                return Ok::<_, ViewError>(selection.into_connection(page, total));
             }
-            _ => vec![],
-        }
+
+            let Some(candles) = self.state.candles.try_load_entry(&(token, interval)).await? else {
+                return Ok(selection.into_connection(Vec::new(), 0));
+            };
+            candles.for_each_index_while(|key| Ok(selection.push(key))).await?;
+            let keys: Vec<u128> = selection.keys.drain(..).collect();
+            let total = if with_total { candles.count().await? } else { 0 };
+            let page = candles
+                .multi_get(&keys)
+                .await?
+                .into_iter()
+                .flatten()
+                .map(|candle| (candle.timestamp, candle));
+            Ok(selection.into_connection(page, total))
+        })
+        .await
     }
 
-    /// Get user's active alerts
-    async fn user_alerts(&self, user_id: String) -> Vec<AlertConfig> {
-        match self.state.alerts.get(&user_id).await {
-            Ok(Some(alerts)) => alerts.to_vec(),
-            _ => vec![],
-        }
+    /// Get OHLC candles starting between `start` and `end` (inclusive,
+    /// defaulting to now), oldest first
+    async fn candle_range(
+        &self,
+        token: String,
+        interval: CandleInterval,
+        start: u64,
+        end: Option<u64>,
+    ) -> Vec<Candle> {
+        let end = end.unwrap_or_else(|| self.runtime.system_time().micros());
+        let token = self.symbol(&token).await;
+        self.state
+            .candle_range(&token, interval, start, end)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Get user's active alerts, paginated by alert id
+    async fn user_alerts(
+        &self,
+        user_id: String,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<String, AlertConfig, TotalCount>> {
+        query(after, before, first, last, |after, before, first, last| async move {
+            let alerts = self.state.alerts.get(&user_id).await?.unwrap_or_default();
+            let mut alerts: BTreeMap<String, AlertConfig> = alerts
+                .into_iter()
+                .map(|alert| (alert.id.clone(), alert))
+                .collect();
+            let total = alerts.len();
+            let mut selection = PageSelection::new(after, before, first, last);
+            for id in alerts.keys() {
+                if !selection.push(id.clone()) {
+                    break;
+                }
+            }
+            let page: Vec<_> = selection
+                .keys
+                .iter()
+                .filter_map(|id| alerts.remove_entry(id))
+                .collect();
+            Ok::<_, ViewError>(selection.into_connection(page, total))
+        })
+        .await
     }
 
     /// Get oracle reputation scores
@@ -140,36 +295,239 @@ impl QueryRoot {
         }
     }
 
-    /// Get all oracle reputations
-    async fn all_oracle_reputations(&self) -> Vec<OracleReputation> {
+    /// Get providers ranked by `metric`, best first. Over a window, scores
+    /// come from the rounds scored within the last `window_secs`; without one,
+    /// from the providers' current reputation.
+    async fn leaderboard(
+        &self,
+        metric: LeaderboardMetric,
+        window_secs: Option<u64>,
+        limit: Option<i32>,
+    ) -> Vec<LeaderboardEntry> {
         let mut reputations = Vec::new();
         let _ = self
             .state
             .oracle_stats
-            .for_each_index_value(|_key, value| {
-                reputations.push(value.into_owned());
+            .for_each_index_value(|_source, reputation| {
+                reputations.push(reputation.into_owned());
                 Ok(())
             })
             .await;
-        reputations
+
+        let now = self.runtime.system_time().micros();
+        let mut entries = Vec::with_capacity(reputations.len());
+        for reputation in reputations {
+            let entry = match window_secs {
+                None => Some(LeaderboardEntry::from_reputation(&reputation)),
+                Some(window_secs) => {
+                    let start = now.saturating_sub(window_secs.saturating_mul(MICROS_PER_SECOND));
+                    let samples = self
+                        .state
+                        .reputation_history(&reputation.source, start, now)
+                        .await
+                        .unwrap_or_default();
+                    LeaderboardEntry::from_samples(&reputation, &samples)
+                }
+            };
+            entries.extend(entry);
+        }
+
+        entries.sort_by(|a, b| metric.compare(a, b).then_with(|| a.source.cmp(&b.source)));
+        let limit = limit.map_or(DEFAULT_PAGE_SIZE, |limit| limit.max(0) as usize);
+        entries.truncate(limit);
+        for (rank, entry) in entries.iter_mut().enumerate() {
+            entry.rank = rank as u32 + 1;
+        }
+        entries
     }
 
-    /// Get number of registered providers
+    /// Get a provider's scored rounds between `start` and `end` (inclusive,
+    /// defaulting to now), oldest first, with its reputation after each
+    async fn reputation_history(
+        &self,
+        source_name: String,
+        start: u64,
+        end: Option<u64>,
+    ) -> Vec<ReputationSample> {
+        let end = end.unwrap_or_else(|| self.runtime.system_time().micros());
+        self.state
+            .reputation_history(&source_name, start, end)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Get how far a provider's latest submissions were from the round
+    /// answers, optionally for one token, newest first
+    async fn deviations(
+        &self,
+        source_name: String,
+        token: Option<String>,
+        limit: Option<i32>,
+    ) -> Vec<ReputationSample> {
+        let limit = limit.map_or(DEFAULT_PAGE_SIZE, |limit| limit.max(0) as usize);
+        let token = match token {
+            Some(token) => Some(self.symbol(&token).await),
+            None => None,
+        };
+        self.state
+            .recent_deviations(&source_name, token.as_deref(), limit)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Get all oracle reputations, paginated by source
+    async fn all_oracle_reputations(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<String, OracleReputation, TotalCount>> {
+        let with_total = wants_total_count(ctx);
+        query(after, before, first, last, |after, before, first, last| {
+            map_connection(&self.state.oracle_stats, after, before, first, last, with_total)
+        })
+        .await
+    }
+
+    /// Get number of active providers
     async fn provider_count(&self) -> i32 {
         self.state.providers.count().await.unwrap_or(0) as i32
     }
 
+    /// Get a registered provider's status and chains
+    async fn provider(&self, source_name: String) -> Option<ProviderRecord> {
+        self.state.provider_record(&source_name).await.ok().flatten()
+    }
+
+    /// Get when providers are quarantined for a poor reputation
+    async fn reputation_policy(&self) -> ReputationPolicy {
+        self.state.reputation_policy.get().clone()
+    }
+
+    /// Get the reputations of the providers left out of aggregation
+    async fn quarantined_providers(&self) -> Vec<OracleReputation> {
+        let mut quarantined = Vec::new();
+        let _ = self
+            .state
+            .oracle_stats
+            .for_each_index_value(|_source, reputation| {
+                if reputation.quarantined {
+                    quarantined.push(reputation.into_owned());
+                }
+                Ok(())
+            })
+            .await;
+        quarantined
+    }
+
+    /// Get the active providers allowed to submit prices for a token
+    async fn token_providers(&self, token: String) -> Vec<String> {
+        let token = self.symbol(&token).await;
+        self.state
+            .token_providers(&token)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(source, _chain_id)| source)
+            .collect()
+    }
+
+    /// Get the tokens a provider may submit prices for
+    async fn provider_tokens(&self, source_name: String) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let _ = self
+            .state
+            .assets
+            .for_each_index_value(|symbol, asset| {
+                if asset.serves(&source_name) {
+                    tokens.push(symbol);
+                }
+                Ok(())
+            })
+            .await;
+        tokens
+    }
+
+    /// Get the registered providers, suspended ones included, paginated by source
+    async fn providers(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<String, ProviderRecord, TotalCount>> {
+        let with_total = wants_total_count(ctx);
+        query(after, before, first, last, |after, before, first, last| {
+            map_connection(&self.state.provider_records, after, before, first, last, with_total)
+        })
+        .await
+    }
+
     /// Get pending submission count for a token
     async fn pending_submission_count(&self, token: String) -> i32 {
-        match self.state.pending_prices.get(&token).await {
-            Ok(Some(pending)) => pending.len() as i32,
+        let token = self.symbol(&token).await;
+        match self.state.open_rounds.get(&token).await {
+            Ok(Some(round)) => round.submissions.len() as i32,
             _ => 0,
         }
     }
 
+    /// Get the submissions of a token's open round, one per source
+    async fn pending_submissions(&self, token: String) -> Vec<OracleInput> {
+        let token = self.symbol(&token).await;
+        match self.state.open_rounds.get(&token).await {
+            Ok(Some(round)) => round.submissions,
+            _ => vec![],
+        }
+    }
+
+    /// Get the latest answered round of a token (Chainlink `latestRoundData`)
+    async fn latest_round_data(&self, token: String) -> Option<RoundData> {
+        let token = self.symbol(&token).await;
+        let round = self.state.latest_answered_round(&token).await.ok()??;
+        round.round_data()
+    }
+
+    /// Get the id of the latest closed round of a token (Chainlink `latestRound`)
+    async fn latest_round(&self, token: String) -> u64 {
+        let token = self.symbol(&token).await;
+        self.state.round_count(&token).await.unwrap_or(0)
+    }
+
+    /// Get the answer of a closed round, among the latest
+    /// `MAX_ROUND_HISTORY_LEN` (Chainlink `getRoundData`)
+    async fn get_round_data(&self, token: String, round_id: u64) -> Option<RoundData> {
+        let token = self.symbol(&token).await;
+        let round = self.state.round(&token, round_id).await.ok()??;
+        round.round_data()
+    }
+
+    /// Get a closed round with its submissions, among the latest
+    /// `MAX_ROUND_HISTORY_LEN`
+    async fn round(&self, token: String, round_id: u64) -> Option<OracleRound> {
+        let token = self.symbol(&token).await;
+        self.state.round(&token, round_id).await.ok()?
+    }
+
+    /// Get the most recent closed rounds of a token, newest first
+    async fn rounds(&self, token: String, limit: Option<i32>) -> Vec<OracleRound> {
+        let limit = limit.unwrap_or(100).max(0) as usize;
+        let token = self.symbol(&token).await;
+        self.state.recent_rounds(&token, limit).await.unwrap_or_default()
+    }
+
+    /// Get the round currently collecting submissions for a token
+    async fn open_round(&self, token: String) -> Option<OracleRound> {
+        let token = self.symbol(&token).await;
+        self.state.open_rounds.get(&token).await.ok()?
+    }
+
     /// Get aggregated network statistics
     async fn network_stats(&self) -> AggregatedStats {
-        let total_queries = self.state.total_queries.get().clone();
+        let total_queries = *self.state.total_queries.get();
 
         // Calculate active oracles
         let mut active_oracles = 0u64;
@@ -187,11 +545,7 @@ impl QueryRoot {
             })
             .await;
 
-        let avg_latency = if active_oracles > 0 {
-            total_latency / active_oracles
-        } else {
-            0
-        };
+        let avg_latency = total_latency.checked_div(active_oracles).unwrap_or(0);
 
         let network_uptime = if active_oracles > 0 {
             total_uptime / active_oracles as f64
@@ -207,29 +561,401 @@ impl QueryRoot {
         }
     }
 
-    /// Get TWAP (Time-Weighted Average Price) for a token
-    async fn twap(&self, token: String) -> Option<f64> {
+    /// Get TWAP (Time-Weighted Average Price) for a token over the last
+    /// `window_secs` (defaults to the token's configured window)
+    async fn twap(&self, token: String, window_secs: Option<u64>) -> Option<Price> {
+        let token = self.symbol(&token).await;
+        let config = self.state.token_config(&token).await.ok()?;
+        let window_secs = window_secs.unwrap_or(config.twap_window_secs);
+        let window = window_secs.saturating_mul(MICROS_PER_SECOND);
+        let now = self.runtime.system_time().micros();
+        let points = self
+            .state
+            .price_points_since(&token, now.saturating_sub(window))
+            .await
+            .ok()?;
+        time_weighted_average(&points, now, window, config.decimals)
+    }
+
+    /// Get aggregation settings for a token
+    async fn token_config(&self, token: String) -> Option<TokenConfig> {
+        self.state.token_config(&token).await.ok()
+    }
+
+    /// Get a supported asset by symbol or `BASE/QUOTE` pair
+    async fn asset(&self, token: String) -> Option<Asset> {
+        self.state.asset(&token).await.ok().flatten()
+    }
+
+    /// Get the supported assets with their pair and metadata, paginated by symbol
+    async fn assets(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<Connection<String, Asset, TotalCount>> {
+        let with_total = wants_total_count(ctx);
+        query(after, before, first, last, |after, before, first, last| {
+            map_connection(&self.state.assets, after, before, first, last, with_total)
+        })
+        .await
+    }
+
+    /// Get VWAP (Volume-Weighted Average Price) for a token over the last
+    /// `window_secs` (defaults to the token's configured window). Falls back to
+    /// the latest round VWAP when no volume was reported in the window.
+    async fn vwap(&self, token: String, window_secs: Option<u64>) -> Option<Price> {
+        let token = self.symbol(&token).await;
+        let config = self.state.token_config(&token).await.ok()?;
+        let window_secs = window_secs.unwrap_or(config.vwap_window_secs);
+        let window = window_secs.saturating_mul(MICROS_PER_SECOND);
+        let now = self.runtime.system_time().micros();
+        let points = self
+            .state
+            .price_points_since(&token, now.saturating_sub(window))
+            .await
+            .ok()?;
+        rolling_volume_weighted_average(&points, now, window, config.decimals)
+            .or_else(|| points.last().map(|point| point.vwap))
+    }
+
+    /// Get the spread of the sources behind a token's latest price
+    async fn confidence(&self, token: String) -> Option<PriceConfidence> {
+        let token = self.symbol(&token).await;
         match self.state.prices.get(&token).await {
-            Ok(Some(data)) => Some(data.twap),
+            Ok(Some(data)) => Some(data.confidence),
             _ => None,
         }
     }
 
-    /// Get VWAP (Volume-Weighted Average Price) for a token
-    async fn vwap(&self, token: String) -> Option<f64> {
-        match self.state.prices.get(&token).await {
-            Ok(Some(data)) => Some(data.vwap),
-            _ => None,
+    /// Get how recent a token's price is and whether it missed its heartbeat
+    async fn price_freshness(&self, token: String) -> Option<PriceFreshness> {
+        let token = self.symbol(&token).await;
+        let price_data = self.state.prices.get(&token).await.ok()??;
+        let config = self.state.token_config(&token).await.ok()?;
+        let now = self.runtime.system_time().micros();
+        Some(PriceFreshness::new(&price_data, &config, now))
+    }
+
+    /// Get every token whose price missed its heartbeat
+    async fn stale_prices(&self) -> Vec<PriceFreshness> {
+        let mut prices = Vec::new();
+        let _ = self
+            .state
+            .prices
+            .for_each_index_value(|_key, value| {
+                prices.push(value.into_owned());
+                Ok(())
+            })
+            .await;
+
+        let now = self.runtime.system_time().micros();
+        let mut stale = Vec::new();
+        for price_data in prices {
+            let Ok(config) = self.state.token_config(&price_data.token).await else {
+                continue;
+            };
+            let freshness = PriceFreshness::new(&price_data, &config, now);
+            if freshness.stale {
+                stale.push(freshness);
+            }
         }
+        stale
     }
 
     /// Get median price for a token
-    async fn median(&self, token: String) -> Option<f64> {
+    async fn median(&self, token: String) -> Option<Price> {
+        let token = self.symbol(&token).await;
         match self.state.prices.get(&token).await {
             Ok(Some(data)) => Some(data.median),
             _ => None,
         }
     }
+
+    /// Get the number of events emitted so far; the next event gets this sequence
+    async fn event_count(&self) -> u64 {
+        *self.state.event_count.get()
+    }
+
+    /// Get the kept events emitted after sequence `after` (all kept events if
+    /// omitted), oldest first, optionally only of some kinds or about a token.
+    /// Clients subscribed to the node's `notifications` call this on each new
+    /// block with the last sequence they have seen.
+    async fn events_since(
+        &self,
+        after: Option<u64>,
+        kinds: Option<Vec<OracleEventKind>>,
+        token: Option<String>,
+        limit: Option<i32>,
+    ) -> Vec<EventEntry> {
+        let limit = limit.map_or(DEFAULT_PAGE_SIZE, |limit| limit.max(0) as usize);
+        let token = match token {
+            Some(token) => Some(self.symbol(&token).await),
+            None => None,
+        };
+        self.state
+            .events_since(after)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|record| kinds.as_ref().is_none_or(|kinds| kinds.contains(&record.event.kind())))
+            .filter(|record| token.is_none() || record.event.token() == token.as_deref())
+            .take(limit)
+            .map(EventEntry::from)
+            .collect()
+    }
+}
+
+impl QueryRoot {
+    /// Symbol under which a token given as its symbol or pair is stored
+    async fn symbol(&self, token: &str) -> String {
+        self.state
+            .symbol(token)
+            .await
+            .unwrap_or_else(|_| normalize_symbol(token))
+    }
+}
+
+/// What a provider leaderboard ranks by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+enum LeaderboardMetric {
+    /// Closeness to the round answers, highest first
+    Accuracy,
+    /// Share of rounds submitted to, highest first
+    Uptime,
+    /// Time from a round's start to the submission, lowest first
+    Latency,
+    /// Number of rounds submitted to, highest first
+    Participation,
+}
+
+impl LeaderboardMetric {
+    /// Order of two entries, better first
+    fn compare(&self, a: &LeaderboardEntry, b: &LeaderboardEntry) -> Ordering {
+        match self {
+            LeaderboardMetric::Accuracy => b.accuracy.total_cmp(&a.accuracy),
+            LeaderboardMetric::Uptime => b.uptime.total_cmp(&a.uptime),
+            // Providers that never submitted have no latency to rank
+            LeaderboardMetric::Latency => (a.participation == 0)
+                .cmp(&(b.participation == 0))
+                .then(a.latency_average.cmp(&b.latency_average)),
+            LeaderboardMetric::Participation => b.participation.cmp(&a.participation),
+        }
+    }
+}
+
+/// A provider's place in a leaderboard
+#[derive(SimpleObject)]
+struct LeaderboardEntry {
+    rank: u32,
+    source: String,
+    accuracy: f64,
+    uptime: f64,
+    latency_average: u64,
+    /// Rounds the provider submitted to, within the window or in total
+    participation: u64,
+    /// Rounds the provider was scored in, within the window or in total
+    rounds: u64,
+    quarantined: bool,
+}
+
+impl LeaderboardEntry {
+    /// Entry from a provider's current reputation
+    fn from_reputation(reputation: &OracleReputation) -> Self {
+        LeaderboardEntry {
+            rank: 0,
+            source: reputation.source.clone(),
+            accuracy: reputation.accuracy,
+            uptime: reputation.uptime,
+            latency_average: reputation.latency_average,
+            participation: reputation.participation,
+            rounds: reputation.rounds,
+            quarantined: reputation.quarantined,
+        }
+    }
+
+    /// Entry from the rounds scored in a window, if there were any
+    fn from_samples(reputation: &OracleReputation, samples: &[ReputationSample]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let accuracies: Vec<f64> = samples
+            .iter()
+            .filter_map(|sample| Some(accuracy_score(sample.deviation?)))
+            .collect();
+        let latencies: Vec<u64> = samples.iter().filter_map(|sample| sample.latency).collect();
+        let participation = samples.iter().filter(|sample| sample.participated).count();
+        Some(LeaderboardEntry {
+            rank: 0,
+            source: reputation.source.clone(),
+            accuracy: if accuracies.is_empty() {
+                0.0
+            } else {
+                accuracies.iter().sum::<f64>() / accuracies.len() as f64
+            },
+            uptime: participation as f64 / samples.len() as f64,
+            latency_average: if latencies.is_empty() {
+                0
+            } else {
+                latencies.iter().sum::<u64>() / latencies.len() as u64
+            },
+            participation: participation as u64,
+            rounds: samples.len() as u64,
+            quarantined: reputation.quarantined,
+        })
+    }
+}
+
+/// An emitted event with its position in the chain's event stream
+#[derive(SimpleObject)]
+struct EventEntry {
+    sequence: u64,
+    timestamp: u64,
+    kind: OracleEventKind,
+    token: Option<String>,
+    /// The event as serialized on the oracle stream
+    event: Json<OracleEvent>,
+}
+
+impl From<OracleEventRecord> for EventEntry {
+    fn from(record: OracleEventRecord) -> Self {
+        EventEntry {
+            sequence: record.sequence,
+            timestamp: record.timestamp,
+            kind: record.event.kind(),
+            token: record.event.token().map(str::to_string),
+            event: Json(record.event),
+        }
+    }
 }
 
+/// Extra fields of the list connections
+#[derive(SimpleObject)]
+struct TotalCount {
+    /// Number of items in the whole list
+    total_count: u64,
+}
+
+/// Whether a list query's selection asks for `totalCount`, which reads the whole list
+fn wants_total_count(ctx: &Context<'_>) -> bool {
+    ctx.look_ahead().field("totalCount").exists()
+}
+
+/// Page selected by Relay-style arguments from keys fed in ascending order.
+/// Keys are only taken until the page is complete, and cursors no longer in
+/// the list still mark a position.
+struct PageSelection<K> {
+    after: Option<K>,
+    before: Option<K>,
+    first: Option<usize>,
+    last: Option<usize>,
+    keys: VecDeque<K>,
+    taken: usize,
+    has_previous: bool,
+    has_next: bool,
+}
+
+impl<K: Ord> PageSelection<K> {
+    fn new(after: Option<K>, before: Option<K>, first: Option<usize>, last: Option<usize>) -> Self {
+        let first = match (first, last) {
+            (None, None) => Some(DEFAULT_PAGE_SIZE),
+            _ => first,
+        };
+        PageSelection {
+            after,
+            before,
+            first,
+            last,
+            keys: VecDeque::new(),
+            taken: 0,
+            has_previous: false,
+            has_next: false,
+        }
+    }
 
+    /// Take the next key, returning whether later keys can still be on the page
+    fn push(&mut self, key: K) -> bool {
+        if self.after.as_ref().is_some_and(|after| key <= *after) {
+            self.has_previous = true;
+            return true;
+        }
+        if self.before.as_ref().is_some_and(|before| key >= *before)
+            || self.first.is_some_and(|first| self.taken >= first)
+        {
+            self.has_next = true;
+            return false;
+        }
+        self.keys.push_back(key);
+        self.taken += 1;
+        if self.last.is_some_and(|last| self.keys.len() > last) {
+            self.keys.pop_front();
+            self.has_previous = true;
+        }
+        true
+    }
+
+    /// Connection holding `page`, the nodes of the selected keys, out of a
+    /// `total`-item list
+    fn into_connection<C, N>(
+        self,
+        page: impl IntoIterator<Item = (C, N)>,
+        total: usize,
+    ) -> Connection<C, N, TotalCount>
+    where
+        C: CursorType + Send + Sync,
+        N: OutputType,
+    {
+        let mut connection = Connection::with_additional_fields(
+            self.has_previous,
+            self.has_next,
+            TotalCount {
+                total_count: total as u64,
+            },
+        );
+        connection
+            .edges
+            .extend(page.into_iter().map(|(cursor, node)| Edge::new(cursor, node)));
+        connection
+    }
+}
+
+/// Sort key of a map key. Map views iterate in the order of the serialized
+/// keys, where a string's length comes before its bytes, so cursors are
+/// compared in that order rather than as strings.
+fn serialized_key(key: String) -> Result<(Vec<u8>, String), ViewError> {
+    Ok((bcs::to_bytes(&key)?, key))
+}
+
+/// Page of a map's values in the map's key order, keyed by their index.
+/// Only the keys up to the end of the page are read, and all of them only
+/// for `with_total`.
+async fn map_connection<V>(
+    map: &MapView<String, V>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<usize>,
+    last: Option<usize>,
+    with_total: bool,
+) -> Result<Connection<String, V, TotalCount>, ViewError>
+where
+    V: Clone + Send + Sync + Serialize + DeserializeOwned + OutputType + 'static,
+{
+    let mut selection = PageSelection::new(
+        after.map(serialized_key).transpose()?,
+        before.map(serialized_key).transpose()?,
+        first,
+        last,
+    );
+    map.for_each_index_while(|key| Ok(selection.push(serialized_key(key)?)))
+        .await?;
+    let keys: Vec<String> = selection.keys.drain(..).map(|(_, key)| key).collect();
+    let values = map.multi_get(&keys).await?;
+    let total = if with_total { map.count().await? } else { 0 };
+    let page = keys
+        .into_iter()
+        .zip(values)
+        .filter_map(|(key, value)| Some((key, value?)));
+    Ok(selection.into_connection(page, total))
+}
@@ -1,44 +1,533 @@
-use linera_sdk::views::{MapView, RegisterView, RootView, ViewStorageContext};
+use linera_sdk::views::{
+    CollectionView, CustomMapView, MapView, QueueView, RegisterView, RootView, ViewError,
+    ViewStorageContext,
+};
 use linera_sdk::linera_base_types::ChainId;
-use oracle_microchain::{AlertConfig, Candle, CandleInterval, OracleReputation, PriceData};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use oracle_microchain::{
+    aggregation::price_confidence, normalize_symbol, AlertConfig, Asset, Candle, CandleInterval,
+    OracleEventRecord, OracleReputation, OracleRound, Price, PriceData, PricePoint, ProviderRecord,
+    ReputationPolicy, ReputationSample, ThresholdType, TokenConfig,
+};
 
 /// Oracle microchain state with full feature set
 #[derive(RootView)]
 #[view(context = ViewStorageContext)]
 pub struct OracleState {
-    /// All price data indexed by token
-    pub prices: MapView<String, PriceData>,
+    /// Legacy price data indexed by token, moved into `prices` by the migration
+    pub legacy_prices: MapView<String, LegacyPriceData>,
     
-    /// Latest price for quick access
-    pub latest_price: RegisterView<Option<PriceData>>,
+    /// Legacy latest price of whichever token published last, cleared by the
+    /// migration; `recent_updates` tracks the latest token
+    pub latest_price: RegisterView<Option<LegacyPriceData>>,
     
     /// Registered oracle providers: source_name -> chain_id
     pub providers: MapView<String, ChainId>,
     
-    /// Pending price submissions for aggregation: token -> Vec<(source, price, timestamp)>
+    /// Legacy pending submissions: token -> Vec<(source, price, timestamp)>,
+    /// cleared by the migration; `open_rounds` holds the submissions
     pub pending_prices: MapView<String, Vec<(String, f64, u64)>>,
     
-    /// 1-second candles: timestamp -> candle
-    pub candles_1s: MapView<String, Vec<Candle>>,
+    /// Legacy 1-second candles, moved into `candles` on the token's next candle write
+    pub candles_1s: MapView<String, Vec<LegacyCandle>>,
     
-    /// 1-minute candles: timestamp -> candle
-    pub candles_1m: MapView<String, Vec<Candle>>,
+    /// Legacy 1-minute candles, moved into `candles` on the token's next candle write
+    pub candles_1m: MapView<String, Vec<LegacyCandle>>,
     
-    /// 1-hour candles: timestamp -> candle
-    pub candles_1h: MapView<String, Vec<Candle>>,
+    /// Legacy 1-hour candles, moved into `candles` on the token's next candle write
+    pub candles_1h: MapView<String, Vec<LegacyCandle>>,
     
-    /// 24-hour candles: timestamp -> candle
-    pub candles_24h: MapView<String, Vec<Candle>>,
+    /// Legacy 24-hour candles, moved into `candles` on the token's next candle write
+    pub candles_24h: MapView<String, Vec<LegacyCandle>>,
     
-    /// User alerts: user_id -> alerts
-    pub alerts: MapView<String, Vec<AlertConfig>>,
+    /// Legacy user alerts, moved into `alerts` by the migration
+    pub legacy_alerts: MapView<String, Vec<LegacyAlertConfig>>,
     
-    /// Oracle reputation tracking: source -> reputation
-    pub oracle_stats: MapView<String, OracleReputation>,
+    /// Legacy oracle reputations, moved into `oracle_stats` by the migration
+    pub legacy_oracle_stats: MapView<String, LegacyOracleReputation>,
     
     /// Total queries counter
     pub total_queries: RegisterView<u64>,
     
     /// Active subscriptions counter
     pub active_subscriptions: RegisterView<u64>,
+
+    /// Whether the legacy views above were moved into their current layout
+    pub migrated: RegisterView<bool>,
+
+    /// All price data indexed by token
+    pub prices: MapView<String, PriceData>,
+
+    /// User alerts: user_id -> alerts
+    pub alerts: MapView<String, Vec<AlertConfig>>,
+
+    /// Oracle reputation tracking: source -> reputation
+    pub oracle_stats: MapView<String, OracleReputation>,
+
+    /// Published aggregates per token, oldest first, for TWAP computation
+    pub price_series: CollectionView<String, QueueView<PricePoint>>,
+
+    /// Round collecting submissions per token
+    pub open_rounds: MapView<String, OracleRound>,
+
+    /// Closed rounds per token by id, bounded by `MAX_ROUND_HISTORY_LEN`
+    pub rounds: CollectionView<String, MapView<u64, OracleRound>>,
+
+    /// Number of closed rounds per token, i.e. the latest round's id
+    pub round_counts: MapView<String, u64>,
+
+    /// Published prices per token, oldest first, bounded by the token's retention
+    pub price_history: CollectionView<String, QueueView<PriceData>>,
+
+    /// OHLC candles per token and interval, keyed by `candle_key` of their start,
+    /// so iteration goes from the newest candle to the oldest
+    pub candles: CollectionView<(String, CandleInterval), CustomMapView<u128, Candle>>,
+
+    /// Latest emitted events, oldest first, bounded by `MAX_EVENT_LOG_LEN`
+    pub events: QueueView<OracleEventRecord>,
+
+    /// Number of events emitted so far, i.e. the next event's sequence
+    pub event_count: RegisterView<u64>,
+
+    /// Tokens keyed by the complement of their latest publication's number,
+    /// so iteration goes from the most recently updated token
+    pub recent_updates: CustomMapView<u128, String>,
+
+    /// Key of each token in `recent_updates`
+    pub recent_update_keys: MapView<String, u128>,
+
+    /// Number of prices published so far
+    pub publication_count: RegisterView<u64>,
+
+    /// Supported assets by symbol
+    pub assets: MapView<String, Asset>,
+
+    /// Symbol of each asset by its `BASE/QUOTE` pair
+    pub asset_pairs: MapView<String, String>,
+
+    /// Status and chains of registered providers, suspended ones included;
+    /// `providers` holds the active ones
+    pub provider_records: MapView<String, ProviderRecord>,
+
+    /// When providers are quarantined for a poor reputation
+    pub reputation_policy: RegisterView<ReputationPolicy>,
+
+    /// Scored rounds per provider, oldest first, bounded by `MAX_REPUTATION_HISTORY_LEN`
+    pub reputation_history: CollectionView<String, QueueView<ReputationSample>>,
+
+    /// Start of the latest candle announced closed per token and interval
+    pub closed_candles: MapView<(String, CandleInterval), u64>,
+}
+
+/// Candle as stored before prices were fixed-point, only read from the
+/// legacy `candles_*` maps
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LegacyCandle {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub timestamp: u64,
+}
+
+impl LegacyCandle {
+    /// The candle with prices at `decimals` decimals, or `None` if one of
+    /// them can't be represented
+    pub fn to_candle(&self, decimals: u8) -> Option<Candle> {
+        Some(Candle {
+            open: Price::from_f64(self.open, decimals)?,
+            high: Price::from_f64(self.high, decimals)?,
+            low: Price::from_f64(self.low, decimals)?,
+            close: Price::from_f64(self.close, decimals)?,
+            volume: if self.volume.is_finite() { self.volume.max(0.0) } else { 0.0 },
+            timestamp: self.timestamp,
+        })
+    }
+}
+
+/// Price data as stored before prices were fixed-point, only read from the
+/// legacy `legacy_prices` map and `latest_price` register
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LegacyPriceData {
+    pub token: String,
+    pub price: f64,
+    pub timestamp: u64,
+    pub source: String,
+    pub network: String,
+    pub median: f64,
+    pub twap: f64,
+    pub vwap: f64,
+}
+
+// Conversions for the contract's migration, unused by the service
+#[allow(dead_code)]
+impl LegacyPriceData {
+    /// The price data under `token` with prices at the decimals of `config`,
+    /// or `None` if one of them can't be represented
+    pub fn to_price_data(&self, token: String, config: &TokenConfig) -> Option<PriceData> {
+        let decimals = config.decimals;
+        let price = Price::from_f64(self.price, decimals)?;
+        let vwap = Price::from_f64(self.vwap, decimals)?;
+        Some(PriceData {
+            token,
+            price,
+            timestamp: self.timestamp,
+            source: self.source.clone(),
+            network: self.network.clone(),
+            median: Price::from_f64(self.median, decimals)?,
+            twap: Price::from_f64(self.twap, decimals)?,
+            twap_window_secs: config.twap_window_secs,
+            vwap,
+            vwap_window_secs: config.vwap_window_secs,
+            round_vwap: vwap,
+            volume: 0.0,
+            mean: price,
+            weighting: config.weighting,
+            confidence: price_confidence(&[price.mantissa], price.mantissa, decimals),
+            round_id: 0,
+        })
+    }
+}
+
+/// Alert as stored before prices were fixed-point, only read from the
+/// legacy `legacy_alerts` map
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LegacyAlertConfig {
+    pub id: String,
+    pub token: String,
+    pub threshold_type: ThresholdType,
+    pub threshold_value: f64,
+    pub active: bool,
+    pub created_at: u64,
+}
+
+// Conversions for the contract's migration, unused by the service
+#[allow(dead_code)]
+impl LegacyAlertConfig {
+    /// The alert on `token` with its threshold at `decimals` decimals, or
+    /// `None` if the threshold can't be represented
+    pub fn to_alert(&self, token: String, decimals: u8) -> Option<AlertConfig> {
+        Some(AlertConfig {
+            id: self.id.clone(),
+            token,
+            threshold_type: self.threshold_type,
+            threshold_value: Price::from_f64(self.threshold_value, decimals)?,
+            active: self.active,
+            created_at: self.created_at,
+        })
+    }
+}
+
+/// Reputation as stored before providers were scored per round, only read
+/// from the legacy `legacy_oracle_stats` map
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LegacyOracleReputation {
+    pub source: String,
+    pub accuracy: f64,
+    pub latency_average: u64,
+    pub uptime: f64,
+    pub variance: f64,
+    pub total_updates: u64,
+    pub last_update: u64,
+}
+
+// Conversions for the contract's migration, unused by the service
+#[allow(dead_code)]
+impl LegacyOracleReputation {
+    /// The reputation with the counters kept before, starting its decay from
+    /// the last update
+    pub fn to_reputation(&self) -> OracleReputation {
+        OracleReputation {
+            accuracy: self.accuracy.clamp(0.0, 1.0),
+            latency_average: self.latency_average,
+            uptime: self.uptime.clamp(0.0, 1.0),
+            variance: self.variance.max(0.0),
+            total_updates: self.total_updates,
+            last_update: self.last_update,
+            scored_at: self.last_update,
+            ..OracleReputation::new(self.source.clone())
+        }
+    }
+}
+
+impl OracleState {
+    /// Settings for a token's asset, falling back to the defaults
+    pub async fn token_config(&self, token: &str) -> Result<TokenConfig, ViewError> {
+        Ok(self
+            .asset(token)
+            .await?
+            .map(|asset| asset.config)
+            .unwrap_or_default())
+    }
+
+    /// A registered provider, including ones registered before records were kept
+    pub async fn provider_record(&self, source: &str) -> Result<Option<ProviderRecord>, ViewError> {
+        if let Some(record) = self.provider_records.get(source).await? {
+            return Ok(Some(record));
+        }
+        let chain_id = self.providers.get(source).await?;
+        Ok(chain_id.map(|chain_id| ProviderRecord::new(source.to_string(), chain_id)))
+    }
+
+    /// Active providers allowed to submit prices for a token, with their chains
+    pub async fn token_providers(&self, token: &str) -> Result<Vec<(String, ChainId)>, ViewError> {
+        let allowed = self.asset(token).await?.and_then(|asset| asset.providers);
+        let Some(sources) = allowed else {
+            let mut providers = Vec::new();
+            self.providers
+                .for_each_index_value(|source, chain_id| {
+                    providers.push((source, chain_id.into_owned()));
+                    Ok(())
+                })
+                .await?;
+            return Ok(providers);
+        };
+        let chains = self.providers.multi_get(&sources).await?;
+        Ok(sources
+            .into_iter()
+            .zip(chains)
+            .filter_map(|(source, chain_id)| Some((source, chain_id?)))
+            .collect())
+    }
+
+    /// Registered asset for a symbol or `BASE/QUOTE` pair, in any case
+    pub async fn asset(&self, token: &str) -> Result<Option<Asset>, ViewError> {
+        let token = normalize_symbol(token);
+        if let Some(asset) = self.assets.get(&token).await? {
+            return Ok(Some(asset));
+        }
+        match self.asset_pairs.get(&token).await? {
+            Some(symbol) => self.assets.get(&symbol).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Symbol under which a token, given as its symbol or `BASE/QUOTE` pair
+    /// in any case, is stored
+    pub async fn symbol(&self, token: &str) -> Result<String, ViewError> {
+        Ok(match self.asset(token).await? {
+            Some(asset) => asset.symbol,
+            None => normalize_symbol(token),
+        })
+    }
+
+    /// Number of closed rounds of a token
+    pub async fn round_count(&self, token: &str) -> Result<u64, ViewError> {
+        Ok(self.round_counts.get(token).await?.unwrap_or(0))
+    }
+
+    /// Candles of a token not yet moved out of the legacy layout, at the
+    /// token's decimals. Candles with unrepresentable prices are dropped.
+    pub async fn legacy_candles(
+        &self,
+        token: &str,
+        interval: CandleInterval,
+    ) -> Result<Option<Vec<Candle>>, ViewError> {
+        let legacy = match interval {
+            CandleInterval::OneSecond => &self.candles_1s,
+            CandleInterval::OneMinute => &self.candles_1m,
+            CandleInterval::OneHour => &self.candles_1h,
+            CandleInterval::TwentyFourHour => &self.candles_24h,
+            _ => return Ok(None),
+        };
+        let Some(candles) = legacy.get(token).await? else {
+            return Ok(None);
+        };
+        let decimals = self.token_config(token).await?.decimals;
+        Ok(Some(
+            candles
+                .iter()
+                .filter_map(|candle| candle.to_candle(decimals))
+                .collect(),
+        ))
+    }
+
+    /// Candles of a token starting within `[start, end]`, oldest first
+    pub async fn candle_range(
+        &self,
+        token: &str,
+        interval: CandleInterval,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<Candle>, ViewError> {
+        let in_range = |candle: &Candle| candle.timestamp >= start && candle.timestamp <= end;
+        if let Some(legacy) = self.legacy_candles(token, interval).await? {
+            return Ok(legacy.into_iter().filter(in_range).collect());
+        }
+        let mut range = Vec::new();
+        let Some(candles) = self.candles.try_load_entry(&(token.to_string(), interval)).await? else {
+            return Ok(range);
+        };
+        candles
+            .for_each_index_value_while(|_key, candle| {
+                if candle.timestamp < start {
+                    return Ok(false);
+                }
+                if candle.timestamp <= end {
+                    range.push(candle.into_owned());
+                }
+                Ok(true)
+            })
+            .await?;
+        range.reverse();
+        Ok(range)
+    }
+
+    /// Price observations for a token from `start` onwards, oldest first.
+    ///
+    /// Includes the last observation before `start` (when there is one) so the
+    /// price in effect at the beginning of the window is known.
+    pub async fn price_points_since(
+        &self,
+        token: &str,
+        start: u64,
+    ) -> Result<Vec<PricePoint>, ViewError> {
+        let Some(series) = self.price_series.try_load_entry(token).await? else {
+            return Ok(Vec::new());
+        };
+        read_back_until(&*series, |point| point.timestamp <= start).await
+    }
+}
+
+/// The newest elements of `queue`, oldest first, going back until `covered`
+/// holds for the oldest one read or the whole queue is read.
+///
+/// Reads backwards in growing chunks, so recent ranges stay cheap.
+async fn read_back_until<T>(
+    queue: &QueueView<T>,
+    covered: impl Fn(&T) -> bool,
+) -> Result<Vec<T>, ViewError>
+where
+    T: Clone + Send + Sync + Serialize + DeserializeOwned,
+{
+    let total = queue.count();
+    let mut count = total.min(16);
+    loop {
+        let values = queue.read_back(count).await?;
+        if values.first().is_some_and(&covered) || count == total {
+            return Ok(values);
+        }
+        count = (count * 2).min(total);
+    }
+}
+
+// Read-only helpers for the service, unused by the contract
+#[allow(dead_code)]
+impl OracleState {
+    /// A kept closed round of a token
+    pub async fn round(&self, token: &str, round_id: u64) -> Result<Option<OracleRound>, ViewError> {
+        match self.rounds.try_load_entry(token).await? {
+            Some(rounds) => rounds.get(&round_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// The most recent kept closed rounds of a token, newest first
+    pub async fn recent_rounds(&self, token: &str, limit: usize) -> Result<Vec<OracleRound>, ViewError> {
+        let Some(rounds) = self.rounds.try_load_entry(token).await? else {
+            return Ok(Vec::new());
+        };
+        let count = self.round_count(token).await?;
+        let first = count.saturating_sub(limit as u64) + 1;
+        let round_ids: Vec<u64> = (first..=count).rev().collect();
+        Ok(rounds.multi_get(&round_ids).await?.into_iter().flatten().collect())
+    }
+
+    /// Kept events with a sequence above `after`, or all kept events, oldest first
+    pub async fn events_since(&self, after: Option<u64>) -> Result<Vec<OracleEventRecord>, ViewError> {
+        let emitted = *self.event_count.get();
+        let newer = after.map_or(emitted, |after| emitted.saturating_sub(after.saturating_add(1)));
+        let count = usize::try_from(newer).unwrap_or(usize::MAX).min(self.events.count());
+        self.events.read_back(count).await
+    }
+
+    /// Latest prices of the `limit` most recently updated tokens, newest first
+    pub async fn recently_updated(&self, limit: usize) -> Result<Vec<PriceData>, ViewError> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let mut tokens = Vec::new();
+        self.recent_updates
+            .for_each_index_value_while(|_key, token| {
+                tokens.push(token.into_owned());
+                Ok(tokens.len() < limit)
+            })
+            .await?;
+        Ok(self.prices.multi_get(&tokens).await?.into_iter().flatten().collect())
+    }
+
+    /// The latest `limit` published prices of a token, newest first
+    pub async fn recent_history(&self, token: &str, limit: usize) -> Result<Vec<PriceData>, ViewError> {
+        let Some(history) = self.price_history.try_load_entry(token).await? else {
+            return Ok(Vec::new());
+        };
+        let mut recent = history.read_back(limit).await?;
+        recent.reverse();
+        Ok(recent)
+    }
+
+    /// Published prices of a token within `[start, end]`, oldest first
+    pub async fn history_range(
+        &self,
+        token: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<PriceData>, ViewError> {
+        let Some(history) = self.price_history.try_load_entry(token).await? else {
+            return Ok(Vec::new());
+        };
+        let mut prices = read_back_until(&*history, |price| price.timestamp < start).await?;
+        prices.retain(|price| price.timestamp >= start && price.timestamp <= end);
+        Ok(prices)
+    }
+
+    /// A provider's rounds scored between `start` and `end` inclusive, oldest first
+    pub async fn reputation_history(
+        &self,
+        source: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<ReputationSample>, ViewError> {
+        let Some(history) = self.reputation_history.try_load_entry(source).await? else {
+            return Ok(Vec::new());
+        };
+        let mut samples = read_back_until(&*history, |sample| sample.timestamp < start).await?;
+        samples.retain(|sample| sample.timestamp >= start && sample.timestamp <= end);
+        Ok(samples)
+    }
+
+    /// A provider's latest `limit` scored submissions with a deviation from
+    /// the answer, optionally for one token, newest first
+    pub async fn recent_deviations(
+        &self,
+        source: &str,
+        token: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ReputationSample>, ViewError> {
+        let Some(history) = self.reputation_history.try_load_entry(source).await? else {
+            return Ok(Vec::new());
+        };
+        let matches = |sample: &ReputationSample| {
+            sample.deviation.is_some() && token.is_none_or(|token| sample.token == token)
+        };
+        let total = history.count();
+        let mut count = total.min(limit.max(16));
+        loop {
+            let samples = history.read_back(count).await?;
+            if samples.iter().filter(|sample| matches(sample)).count() >= limit || count == total {
+                return Ok(samples.into_iter().rev().filter(matches).take(limit).collect());
+            }
+            count = (count * 2).min(total);
+        }
+    }
+
+    /// The most recent round of a token that produced an answer, the one
+    /// behind its latest price
+    pub async fn latest_answered_round(&self, token: &str) -> Result<Option<OracleRound>, ViewError> {
+        let Some(price_data) = self.prices.get(token).await? else {
+            return Ok(None);
+        };
+        self.round(token, price_data.round_id).await
+    }
 }
@@ -183,6 +183,24 @@ sleep 2
 
 echo "✅ Providers registered"
 
+# ----------------------------------------------------------
+# Register Assets
+# ----------------------------------------------------------
+echo "📝 Registering assets..."
+
+# Submissions and alerts are only accepted for registered assets
+for ASSET in "ETH:Ethereum" "BTC:Bitcoin" "SOL:Solana" "MATIC:Polygon" "LINK:Chainlink"; do
+  SYMBOL="${ASSET%%:*}"
+  NAME="${ASSET#*:}"
+  MUTATION="mutation { registerAsset(symbol: \\\"$SYMBOL\\\", base: \\\"$SYMBOL\\\", quote: \\\"USD\\\", displayName: \\\"$NAME\\\") }"
+  curl -s -X POST "$GRAPHQL_URL/chains/$MASTER_CHAIN_ID/applications/$ORACLE_APP_ID" \
+    -H "Content-Type: application/json" \
+    -d "{\"query\":\"$MUTATION\"}" | jq .
+done
+sleep 2
+
+echo "✅ Assets registered"
+
 # ----------------------------------------------------------
 # Generate Frontend Config
 # ----------------------------------------------------------